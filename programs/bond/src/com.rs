@@ -1,201 +1,51 @@
-use anchor_lang::prelude::*;
+use crate::decimal::Decimal;
 // use anchor_spl::mint;
-use serde::{Deserialize, Serialize};
-use std::convert::TryFrom;
-use std::fmt;
+// Default risk-control thresholds of new markets, see `market::RiskParams`.
 /// The exposure ratio should not exceed 70% of the current pool,
 /// so as to avoid the risk that the platform's current pool is empty.
-pub const POSITION_DIFF_PROPORTION: f64 = 0.7;
+pub const POSITION_DIFF_PROPORTION: Decimal = Decimal::new(7, 1);
 /// The proportion of unidirectional positions shall not exceed 150% of the flow pool,
 /// so as to avoid the risk of malicious position opening.
-pub const POSITION_PROPORTION: f64 = 1.5;
+pub const POSITION_PROPORTION: Decimal = Decimal::new(15, 1);
 /// The size of a single position shall not be greater than 20% of the exposure
-pub const POSITION_PROPORTION_ONE: f64 = 0.2;
+pub const POSITION_PROPORTION_ONE: Decimal = Decimal::new(2, 1);
 /// Funding rate, which is 1% of the proportion of exposed funds in the liquidity pool.
 ///  For example, when the exposure proportion is 70%, this value is 7/1000.
-pub const FUND_RATE: f64 = 0.01;
+pub const FUND_RATE: Decimal = Decimal::new(1, 2);
+/// Exposure proportion at which the funding rate stops growing,
+/// also used when the liquidity pool is empty or overdrawn.
+pub const MAX_FUNDING_EXPOSURE_PROPORTION: Decimal = Decimal::ONE;
+/// Funding accrues continuously, `FUND_RATE` is charged once per funding period (seconds).
+pub const FUNDING_PERIOD: i64 = 24 * 60 * 60;
+/// The market management rate is charged on the fund size of a position once per period (seconds).
+pub const MANAGEMENT_FEE_PERIOD: i64 = 24 * 60 * 60;
 /// The liquidation line ratio means that if the user's margin loss exceeds this ratio in one quotation,
 /// the system will be liquidated and the position will be forced to close.
-pub const BURST_RATE: f64 = 0.5;
+/// Full positions share the account margin across markets and always use this value.
+pub const BURST_RATE: Decimal = Decimal::new(5, 1);
 pub const MAX_LEVERAGE: u16 = 125;
+/// A divestment request can be executed during this period (seconds) once its cooldown has passed,
+/// after that it is void and has to be requested again.
+pub const DIVESTMENT_EXECUTION_WINDOW: i64 = 24 * 60 * 60;
+/// Lowest LP share price used to mint shares, the insurance fund tops a pool whose net asset
+/// value was wiped out up to it so the pool can be recapitalised.
+pub const MIN_SHARE_PRICE: Decimal = Decimal::new(1, 2);
+/// The bond boost rate of a market is paid per period of lock term (seconds).
+pub const BOND_BOOST_PERIOD: i64 = 365 * 24 * 60 * 60;
+/// Longest lock term of a bond (seconds).
+pub const BOND_MAX_LOCK_TERM: i64 = 4 * BOND_BOOST_PERIOD;
+/// Name suffix and symbol of the bond NFT metadata
+pub const BOND_NAME_SUFFIX: &str = " Scale Bond";
+pub const BOND_SYMBOL: &str = "SBOND";
 
 pub const VAULT_TOKEN_ACCOUNT_SEED: &[u8] = b"scale_vault";
 pub const VAULT_TOKEN_AUTHORITY_SEED: &[u8] = b"scale_vault_authority";
 pub const USER_ACCOUNT_SEED: &[u8] = b"scale_user_account";
 pub const MARKET_ACCOUNT_SEED: &[u8] = b"scale_market_account";
 pub const POSITION_ACCOUNT_SEED: &[u8] = b"scale_position_account";
-pub const DECIMALS: f64 = 1000000.0;
+pub const CONFIG_ACCOUNT_SEED: &[u8] = b"scale_config_account";
+pub const LP_MINT_SEED: &[u8] = b"scale_lp_mint";
+pub const INVESTOR_ACCOUNT_SEED: &[u8] = b"scale_investor_account";
+pub const BOND_ACCOUNT_SEED: &[u8] = b"scale_bond_account";
+pub const BOND_ESCROW_SEED: &[u8] = b"scale_bond_escrow";
 // pub const PRICE_ACCOUNT:[]
-#[cfg(feature = "mainnet")]
-pub mod base_account {
-    use super::*;
-    /// Mint address of the vault token, the test version is the circulating token issued by the project,
-    /// and the official network is USDC
-    pub fn get_vault_mint() -> Pubkey {
-        // mint::USDC
-        Pubkey::try_from("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v").unwrap()
-    }
-
-    /// Address of project development fund wallet
-    pub fn get_project_fund_wallet() -> Pubkey {
-        Pubkey::try_from("Bu91vdLYSmiip8fS7ijzTcFAnu3TNCUA7kfj2pRMzC9T").unwrap()
-    }
-    /// Insurance fund address
-    pub fn get_insurance_fund_wallet() -> Pubkey {
-        Pubkey::try_from("Bu91vdLYSmiip8fS7ijzTcFAnu3TNCUA7kfj2pRMzC9T").unwrap()
-    }
-    /// The team authorized account is used to initialize and set the official trading market
-    pub fn get_team_authority() -> Pubkey {
-        Pubkey::try_from("DqFNrbXm9qanh2tUh9kURvEyx3PUe8dKDYXvtkBxThXm").unwrap()
-    }
-
-    pub fn get_pyth_price_account_btc() -> Pubkey {
-        Pubkey::try_from("GVXRSBjFk6e6J3NbVPXohDJetcTjaeeuykUpbQF8UoMU").unwrap()
-    }
-    pub fn get_pyth_price_account_eth() -> Pubkey {
-        Pubkey::try_from("JBu1AL4obBcCMqKBBxhpWCNUt136ijcuMZLFvTP7iWdB").unwrap()
-    }
-    pub fn get_pyth_price_account_sol() -> Pubkey {
-        Pubkey::try_from("H6ARHf6YXhGYeQfUzQNGk6rDNnLBQKrenN712K4AQJEG").unwrap()
-    }
-    pub fn get_chainlink_price_account_btc() -> Pubkey {
-        Pubkey::try_from("CGmWwBNsTRDENT5gmVZzRu38GnNnMm1K5C3sFiUUyYQX").unwrap()
-    }
-    pub fn get_chainlink_price_account_eth() -> Pubkey {
-        Pubkey::try_from("5WyTBrEgvkAXjTdYTLY9PVrztjmz4edP5W9wks9KPFg5").unwrap()
-    }
-    pub fn get_chainlink_price_account_sol() -> Pubkey {
-        Pubkey::try_from("CcPVS9bqyXbD9cLnTbhhHazLsrua8QMFUHTutPtjyDzq").unwrap()
-    }
-    pub fn get_clearing_robot() -> Pubkey {
-        Pubkey::try_from("DqFNrbXm9qanh2tUh9kURvEyx3PUe8dKDYXvtkBxThXm").unwrap()
-    }
-}
-#[cfg(feature = "testnet")]
-pub mod base_account {
-    use super::*;
-    /// Mint address of the vault token, the test version is the circulating token issued by the project,
-    /// and the official network is USDC
-    pub fn get_vault_mint() -> Pubkey {
-        Pubkey::try_from("6fsNvxjjePiMGeHUFKUMhjG9CGAVRVwwQjaUJPrLYUu3").unwrap()
-    }
-    /// Address of project development fund wallet
-    pub fn get_project_fund_wallet() -> Pubkey {
-        Pubkey::try_from("Bu91vdLYSmiip8fS7ijzTcFAnu3TNCUA7kfj2pRMzC9T").unwrap()
-    }
-    /// Insurance fund address
-    pub fn get_insurance_fund_wallet() -> Pubkey {
-        Pubkey::try_from("Bu91vdLYSmiip8fS7ijzTcFAnu3TNCUA7kfj2pRMzC9T").unwrap()
-    }
-    /// The team authorized account is used to initialize and set the official trading market
-    pub fn get_team_authority() -> Pubkey {
-        Pubkey::try_from("DqFNrbXm9qanh2tUh9kURvEyx3PUe8dKDYXvtkBxThXm").unwrap()
-    }
-    pub fn get_pyth_price_account_btc() -> Pubkey {
-        Pubkey::try_from("DJW6f4ZVqCnpYNN9rNuzqUcCvkVtBgixo8mq9FKSsCbJ").unwrap()
-    }
-    pub fn get_pyth_price_account_eth() -> Pubkey {
-        Pubkey::try_from("7A98y76fcETLHnkCxjmnUrsuNrbUae7asy4TiVeGqLSs").unwrap()
-    }
-    pub fn get_pyth_price_account_sol() -> Pubkey {
-        Pubkey::try_from("7VJsBtJzgTftYzEeooSDYyjKXvYRWJHdwvbwfBvTg9K").unwrap()
-    }
-    pub fn get_chainlink_price_account_btc() -> Pubkey {
-        Pubkey::try_from("").unwrap()
-    }
-    pub fn get_chainlink_price_account_eth() -> Pubkey {
-        Pubkey::try_from("").unwrap()
-    }
-    pub fn get_chainlink_price_account_sol() -> Pubkey {
-        Pubkey::try_from("").unwrap()
-    }
-    pub fn get_clearing_robot() -> Pubkey {
-        Pubkey::try_from("DqFNrbXm9qanh2tUh9kURvEyx3PUe8dKDYXvtkBxThXm").unwrap()
-    }
-}
-#[cfg(any(feature = "devnet", feature = "localhost"))]
-pub mod base_account {
-    use super::*;
-    /// Mint address of the vault token, the test version is the circulating token issued by the project,
-    /// and the official network is USDC
-    pub fn get_vault_mint() -> Pubkey {
-        Pubkey::try_from("3xJL46KjjDQbPUDg54nEzSC1Ejs49xFHwQJyEMPq7H7g").unwrap()
-    }
-    /// Address of project development fund wallet
-    pub fn get_project_fund_wallet() -> Pubkey {
-        Pubkey::try_from("Bu91vdLYSmiip8fS7ijzTcFAnu3TNCUA7kfj2pRMzC9T").unwrap()
-    }
-    /// Insurance fund address
-    pub fn get_insurance_fund_wallet() -> Pubkey {
-        Pubkey::try_from("Bu91vdLYSmiip8fS7ijzTcFAnu3TNCUA7kfj2pRMzC9T").unwrap()
-    }
-    /// The team authorized account is used to initialize and set the official trading market
-    pub fn get_team_authority() -> Pubkey {
-        Pubkey::try_from("DqFNrbXm9qanh2tUh9kURvEyx3PUe8dKDYXvtkBxThXm").unwrap()
-    }
-    pub fn get_pyth_price_account_btc() -> Pubkey {
-        Pubkey::try_from("HovQMDrbAgAYPCmHVSrezcSmkMtXSSUsLDFANExrZh2J").unwrap()
-    }
-    pub fn get_pyth_price_account_eth() -> Pubkey {
-        Pubkey::try_from("EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw").unwrap()
-    }
-    pub fn get_pyth_price_account_sol() -> Pubkey {
-        Pubkey::try_from("J83w4HKfqxwcq3BEMMkPFSppX3gqekLyLJBexebFVkix").unwrap()
-    }
-    pub fn get_chainlink_price_account_btc() -> Pubkey {
-        Pubkey::try_from("CzZQBrJCLqjXRfMjRN3fhbxur2QYHUzkpaRwkWsiPqbz").unwrap()
-    }
-    pub fn get_chainlink_price_account_eth() -> Pubkey {
-        Pubkey::try_from("2ypeVyYnZaW2TNYXXTaZq9YhYvnqcjCiifW1C6n8b7Go").unwrap()
-    }
-    pub fn get_chainlink_price_account_sol() -> Pubkey {
-        Pubkey::try_from("HgTtcbcmp5BeThax5AU8vg4VwK79qAvAKKFMs8txMLW6").unwrap()
-    }
-    pub fn get_clearing_robot() -> Pubkey {
-        Pubkey::try_from("DqFNrbXm9qanh2tUh9kURvEyx3PUe8dKDYXvtkBxThXm").unwrap()
-    }
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Deserialize, Serialize)]
-pub enum FullPositionMarket {
-    BtcUsd = 1,
-    EthUsd,
-    SolUsd,
-    None,
-}
-
-impl FullPositionMarket {
-    pub fn to_pubkey(self) -> (Pubkey, u8) {
-        Pubkey::find_program_address(
-            &[MARKET_ACCOUNT_SEED, self.to_string().as_bytes()],
-            &crate::id(),
-        )
-    }
-}
-
-impl<'a> From<&'a str> for FullPositionMarket {
-    fn from(value: &'a str) -> Self {
-        let c = value.as_bytes();
-        match c {
-            b"BTC/USD" => Self::BtcUsd,
-            b"ETH/USD" => Self::EthUsd,
-            b"SOL/USD" => Self::SolUsd,
-            _ => Self::None,
-        }
-    }
-}
-impl fmt::Display for FullPositionMarket {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let t = match *self {
-            Self::BtcUsd => "BTC/USD",
-            Self::EthUsd => "ETH/USD",
-            Self::SolUsd => "SOL/USD",
-            Self::None => "None",
-        };
-        write!(f, "{}", t)
-    }
-}
-
-pub fn f64_round(f: f64) -> f64 {
-    (f * 100.0).round() / 100.0
-}
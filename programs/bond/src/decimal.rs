@@ -0,0 +1,283 @@
+use crate::errors::BondError;
+use anchor_lang::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Signed fixed-point number with `Decimal::DECIMAL_PLACES` fractional digits.
+///
+/// The scale is the same as the vault token (6 decimals, USDC), so a token amount
+/// maps onto `value` without any rounding and on-chain balances reconcile exactly
+/// with the SPL vault. Prices, sizes and rates use the same representation.
+/// All arithmetic is checked and fails with `BondError::MathOverflow`.
+#[derive(
+    AnchorSerialize,
+    AnchorDeserialize,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Deserialize,
+    Serialize,
+)]
+pub struct Decimal {
+    value: i64,
+}
+
+impl Decimal {
+    pub const LEN: usize = 8;
+    pub const DECIMAL_PLACES: u32 = 6;
+    pub const SCALE: i64 = 10i64.pow(Self::DECIMAL_PLACES);
+    pub const ZERO: Decimal = Decimal { value: 0 };
+    pub const ONE: Decimal = Decimal { value: Self::SCALE };
+
+    /// Build a constant from an integer mantissa and a number of decimal places,
+    /// e.g. `Decimal::new(3, 3)` is 0.003.
+    pub const fn new(mantissa: i64, decimals: u32) -> Self {
+        Decimal {
+            value: mantissa * 10i64.pow(Self::DECIMAL_PLACES - decimals),
+        }
+    }
+
+    pub const fn from_raw(value: i64) -> Self {
+        Decimal { value }
+    }
+
+    pub const fn raw(&self) -> i64 {
+        self.value
+    }
+
+    /// Instruction parameters are passed as unsigned raw fixed-point values.
+    pub fn from_raw_u64(value: u64) -> Result<Self> {
+        let value = i64::try_from(value).map_err(|_| BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    /// Token amounts in the smallest unit of the vault mint.
+    pub fn from_token_amount(amount: u64) -> Result<Self> {
+        Self::from_raw_u64(amount)
+    }
+
+    pub fn to_token_amount(self) -> Result<u64> {
+        u64::try_from(self.value).map_err(|_| BondError::MathOverflow.into())
+    }
+
+    pub fn from_integer(n: i64) -> Result<Self> {
+        let value = n.checked_mul(Self::SCALE).ok_or(BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    /// Convert an oracle quote `mantissa * 10^expo`, truncating digits beyond the scale.
+    pub fn from_price(mantissa: i64, expo: i32) -> Result<Self> {
+        let shift = expo + Self::DECIMAL_PLACES as i32;
+        if shift >= 0 {
+            let pow = 10i128
+                .checked_pow(shift.unsigned_abs())
+                .ok_or(BondError::MathOverflow)?;
+            let value = (mantissa as i128)
+                .checked_mul(pow)
+                .ok_or(BondError::MathOverflow)?;
+            return Self::from_i128(value);
+        }
+        // an i64 mantissa has at most 19 digits, any larger shift truncates it to zero
+        let value = match 10i64.checked_pow(shift.unsigned_abs()) {
+            Some(pow) => mantissa / pow,
+            None => 0,
+        };
+        Ok(Decimal { value })
+    }
+
+    fn from_i128(value: i128) -> Result<Self> {
+        let value = i64::try_from(value).map_err(|_| BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    pub fn checked_add(self, rhs: Decimal) -> Result<Self> {
+        let value = self
+            .value
+            .checked_add(rhs.value)
+            .ok_or(BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    pub fn checked_sub(self, rhs: Decimal) -> Result<Self> {
+        let value = self
+            .value
+            .checked_sub(rhs.value)
+            .ok_or(BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    /// Product truncated toward zero.
+    pub fn checked_mul(self, rhs: Decimal) -> Result<Self> {
+        let value = (self.value as i128)
+            .checked_mul(rhs.value as i128)
+            .ok_or(BondError::MathOverflow)?
+            / Self::SCALE as i128;
+        Self::from_i128(value)
+    }
+
+    /// Quotient truncated toward zero, division by zero is an overflow.
+    pub fn checked_div(self, rhs: Decimal) -> Result<Self> {
+        if rhs.value == 0 {
+            return Err(BondError::MathOverflow.into());
+        }
+        let value = (self.value as i128)
+            .checked_mul(Self::SCALE as i128)
+            .ok_or(BondError::MathOverflow)?
+            / rhs.value as i128;
+        Self::from_i128(value)
+    }
+
+    /// `self * num / den` without intermediate rounding, truncated toward zero.
+    pub fn checked_mul_div(self, num: Decimal, den: Decimal) -> Result<Self> {
+        if den.value == 0 {
+            return Err(BondError::MathOverflow.into());
+        }
+        let value = (self.value as i128)
+            .checked_mul(num.value as i128)
+            .ok_or(BondError::MathOverflow)?
+            / den.value as i128;
+        Self::from_i128(value)
+    }
+
+    pub fn checked_mul_int(self, n: i64) -> Result<Self> {
+        let value = self.value.checked_mul(n).ok_or(BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    pub fn checked_div_int(self, n: i64) -> Result<Self> {
+        let value = self.value.checked_div(n).ok_or(BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    pub fn checked_neg(self) -> Result<Self> {
+        let value = self.value.checked_neg().ok_or(BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    pub fn checked_abs(self) -> Result<Self> {
+        let value = self.value.checked_abs().ok_or(BondError::MathOverflow)?;
+        Ok(Decimal { value })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.value == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.value < 0
+    }
+
+    pub fn is_positive(&self) -> bool {
+        self.value > 0
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.value < 0 { "-" } else { "" };
+        let abs = (self.value as i128).abs();
+        let scale = Self::SCALE as i128;
+        write!(
+            f,
+            "{}{}.{:0width$}",
+            sign,
+            abs / scale,
+            abs % scale,
+            width = Self::DECIMAL_PLACES as usize
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mul_div_truncate_toward_zero() {
+        let a = Decimal::new(1, 6);
+        let half = Decimal::new(5, 1);
+        // 0.000001 * 0.5 is below the scale
+        assert_eq!(a.checked_mul(half).unwrap(), Decimal::ZERO);
+        assert_eq!(
+            a.checked_neg().unwrap().checked_mul(half).unwrap(),
+            Decimal::ZERO
+        );
+        let third = Decimal::ONE
+            .checked_div(Decimal::from_integer(3).unwrap())
+            .unwrap();
+        assert_eq!(third, Decimal::from_raw(333_333));
+        let minus_third = Decimal::ONE
+            .checked_neg()
+            .unwrap()
+            .checked_div(Decimal::from_integer(3).unwrap())
+            .unwrap();
+        assert_eq!(minus_third, Decimal::from_raw(-333_333));
+        // no intermediate rounding
+        let v = Decimal::from_integer(10)
+            .unwrap()
+            .checked_mul_div(Decimal::ONE, Decimal::from_integer(3).unwrap())
+            .unwrap();
+        assert_eq!(v, Decimal::from_raw(3_333_333));
+        assert_eq!(
+            Decimal::new(7, 0).checked_div_int(2).unwrap(),
+            Decimal::new(35, 1)
+        );
+    }
+
+    #[test]
+    fn test_from_price_exponents() {
+        assert_eq!(
+            Decimal::from_price(2_000_012_345_678, -8).unwrap(),
+            Decimal::from_raw(20_000_123_456)
+        );
+        assert_eq!(Decimal::from_price(15, 0).unwrap(), Decimal::new(15, 0));
+        assert_eq!(Decimal::from_price(15, 2).unwrap(), Decimal::new(1500, 0));
+        assert_eq!(Decimal::from_price(15, -6).unwrap(), Decimal::from_raw(15));
+        assert_eq!(Decimal::from_price(15, -7).unwrap(), Decimal::from_raw(1));
+        assert_eq!(Decimal::from_price(-15, -7).unwrap(), Decimal::from_raw(-1));
+        // large negative exponents truncate to zero
+        assert_eq!(Decimal::from_price(i64::MAX, -30).unwrap(), Decimal::ZERO);
+        assert_eq!(Decimal::from_price(i64::MIN, -60).unwrap(), Decimal::ZERO);
+        // large positive exponents overflow
+        assert!(Decimal::from_price(1, 20).is_err());
+        assert!(Decimal::from_price(1, 60).is_err());
+    }
+
+    #[test]
+    fn test_overflow_and_division_by_zero() {
+        let max = Decimal::from_raw(i64::MAX);
+        assert!(max.checked_add(Decimal::from_raw(1)).is_err());
+        assert!(Decimal::from_raw(i64::MIN)
+            .checked_sub(Decimal::from_raw(1))
+            .is_err());
+        assert!(max.checked_mul(Decimal::from_integer(2).unwrap()).is_err());
+        assert!(max.checked_mul_int(2).is_err());
+        assert!(Decimal::from_raw(i64::MIN).checked_neg().is_err());
+        assert!(Decimal::from_raw(i64::MIN).checked_abs().is_err());
+        assert!(Decimal::from_integer(i64::MAX).is_err());
+        assert!(Decimal::ONE.checked_div(Decimal::ZERO).is_err());
+        assert!(Decimal::ONE
+            .checked_mul_div(Decimal::ONE, Decimal::ZERO)
+            .is_err());
+        assert!(Decimal::ONE.checked_div_int(0).is_err());
+    }
+
+    #[test]
+    fn test_token_amount_conversions() {
+        assert_eq!(
+            Decimal::from_token_amount(1_500_000).unwrap(),
+            Decimal::new(15, 1)
+        );
+        assert_eq!(Decimal::new(15, 1).to_token_amount().unwrap(), 1_500_000);
+        assert!(Decimal::from_token_amount(u64::MAX).is_err());
+        assert!(Decimal::new(-1, 6).to_token_amount().is_err());
+        assert_eq!(Decimal::new(-15, 1).to_string(), "-1.500000");
+    }
+}
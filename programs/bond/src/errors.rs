@@ -56,4 +56,50 @@ pub enum BondError {
     PositionStatusInvalid,
     #[msg("The price is invalid")]
     PriceError,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("The oracle prices diverge beyond the market threshold")]
+    PriceDivergenceTooLarge,
+    #[msg("The oracle price is stale")]
+    StalePrice,
+    #[msg("The oracle price confidence interval is too wide")]
+    PriceConfidenceTooLow,
+    #[msg("Insufficient free balance to withdraw")]
+    InsufficientFreeBalance,
+    #[msg("The position is above the liquidation line")]
+    PositionNotLiquidatable,
+    #[msg("The trigger price is on the wrong side of the current price")]
+    InvalidTriggerPrice,
+    #[msg("The trigger price has not been reached")]
+    TriggerNotReached,
+    #[msg("The order has expired")]
+    OrderExpired,
+    #[msg("The order price has not been reached")]
+    OrderPriceNotReached,
+    #[msg("The market parameter is invalid")]
+    InvalidMarketParameter,
+    #[msg("The leverage exceeds the limit of the market for this position size")]
+    LeverageTooHigh,
+    #[msg("The full position market registry is full")]
+    FullPositionRegistryFull,
+    #[msg("The net asset value of the liquidity pool is not positive")]
+    PoolNavNotPositive,
+    #[msg("The shares exceed the LP tokens held")]
+    InsufficientShares,
+    #[msg("The lock term of the bond is invalid")]
+    InvalidBondLockTerm,
+    #[msg("The bond is still locked")]
+    BondLocked,
+    #[msg("No divestment has been requested")]
+    DivestmentNotRequested,
+    #[msg("The divestment cooldown has not passed")]
+    DivestmentCooldown,
+    #[msg("The divestment would push the exposure above the limit of the market")]
+    DivestmentExceedsExposureLimit,
+    #[msg("The same position account is passed more than once")]
+    DuplicatePositionAccount,
+    #[msg("The divestment request has expired")]
+    DivestmentRequestExpired,
+    #[msg("The uri of the bond metadata is too long")]
+    BondUriTooLong,
 }
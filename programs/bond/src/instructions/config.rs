@@ -0,0 +1,133 @@
+use crate::{
+    com,
+    errors::BondError,
+    program::Bond,
+    state::{
+        config::{self, ConfigParams},
+        market,
+    },
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::Mint;
+
+/// Create the protocol config, only the program upgrade authority can do it.
+/// The initializer becomes the governance authority, the vault mint cannot change afterwards.
+pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+    let config_account = &mut ctx.accounts.config_account;
+    config_account.authority = ctx.accounts.authority.key();
+    config_account.vault_mint = ctx.accounts.vault_mint.key();
+    config_account.set_params(&params);
+    msg!(
+        "initialize config vault_mint:{:?},{:?}",
+        config_account.vault_mint,
+        params
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer=authority,
+        space=config::Config::LEN + 8,
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    pub vault_mint: Account<'info, Mint>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key())@BondError::NoPermission)]
+    pub program: Program<'info, Bond>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(authority.key())@BondError::NoPermission)]
+    pub program_data: Account<'info, ProgramData>,
+    system_program: Program<'info, System>,
+}
+
+pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+    ctx.accounts.config_account.set_params(&params);
+    msg!("update config:{:?}", params);
+    Ok(())
+}
+
+/// Hand the governance of the config over to a new authority.
+pub fn transfer_config_authority(ctx: Context<UpdateConfig>, new_authority: Pubkey) -> Result<()> {
+    if new_authority == Pubkey::default() {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    msg!(
+        "transfer config authority from:{:?},to:{:?}",
+        ctx.accounts.config_account.authority,
+        new_authority
+    );
+    ctx.accounts.config_account.authority = new_authority;
+    Ok(())
+}
+
+/// Register a market in the full position registry, the market joins the full position equity.
+/// A market removed before gets its previous id back.
+pub fn add_full_position_market(
+    ctx: Context<UpdateFullPositionMarket>,
+    pair: String,
+) -> Result<()> {
+    let accounts = &mut *ctx.accounts;
+    let market_account = &mut accounts.market_account;
+    market_account.full_position_id = accounts
+        .config_account
+        .register_full_position_market(market_account.key(), market_account.full_position_id)?;
+    market_account.is_support_full_position = true;
+    msg!(
+        "add full position market pair:{:?},id:{}",
+        pair,
+        market_account.full_position_id
+    );
+    Ok(())
+}
+
+/// Stop opening full positions on a market.
+/// The registry slot is kept so the existing positions are still evaluated and can be closed.
+pub fn remove_full_position_market(
+    ctx: Context<UpdateFullPositionMarket>,
+    pair: String,
+) -> Result<()> {
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.is_support_full_position = false;
+    msg!(
+        "remove full position market pair:{:?},id:{}",
+        pair,
+        market_account.full_position_id
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct UpdateFullPositionMarket<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority@BondError::NoPermission,
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(
+        mut,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority@BondError::NoPermission,
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+}
@@ -0,0 +1,335 @@
+use crate::{
+    com,
+    decimal::Decimal,
+    errors::BondError,
+    instructions::position::*,
+    state::{config, equity, market, position, user},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+
+/// Force close a position whose equity ratio fell below the liquidation line.
+/// Anyone can call it, the caller is paid a share of the liquidation penalty.
+pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
+    let market_account = &mut ctx.accounts.market_account;
+    let user_account = &mut ctx.accounts.user_account;
+    let position_account = &mut ctx.accounts.position_account;
+    if market_account.status == market::MarketStatus::Frozen {
+        return Err(BondError::MarketFrozen.into());
+    }
+    if position_account.position_status != position::PositionStatus::Normal {
+        return Err(BondError::PositionStatusInvalid.into());
+    }
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let fund_size = position_account.get_fund_size()?;
+    let now = Clock::get()?.unix_timestamp;
+    market_account.update_funding(now)?;
+    // equity ratio of the position, or of the whole account in full position mode
+    let (equity, margin, burst_rate) = match position_account.position_type {
+        position::PositionType::Independent => {
+            let valuation = equity::value_position(
+                &position_account.get_header(market_account.full_position_id),
+                market_account,
+                &price,
+                now,
+            )?;
+            (
+                position_account.margin.checked_add(valuation.get_pl()?)?,
+                position_account.margin,
+                market_account.risk_params.burst_rate,
+            )
+        }
+        position::PositionType::Full => {
+            let mut full_position_markets = load_full_position_markets(ctx.remaining_accounts)?;
+            refresh_full_position_market(
+                &mut full_position_markets,
+                &position_account.market_account,
+                market_account,
+            );
+            (
+                get_full_position_equity(user_account, &full_position_markets)?.get_equity()?,
+                user_account.get_full_position_margin(),
+                com::BURST_RATE,
+            )
+        }
+    };
+    msg!("liquidate position equity:{},margin:{}", equity, margin);
+    if !margin.is_positive() || equity.checked_div(margin)? >= burst_rate {
+        return Err(BondError::PositionNotLiquidatable.into());
+    }
+    let balance_before = user_account.balance;
+    let total_pl = settle_position(
+        market_account,
+        user_account,
+        position_account,
+        &price,
+        position::PositionStatus::ForceClosing,
+        ctx.accounts.liquidator.key(),
+    )?;
+    // The penalty is taken from what is left to the user after settlement
+    let remaining = match position_account.position_type {
+        position::PositionType::Independent => user_account.balance.checked_sub(balance_before)?,
+        position::PositionType::Full => user_account.balance,
+    };
+    let penalty = fund_size
+        .checked_mul(market_account.liquidation_penalty_rate)?
+        .min(remaining.max(Decimal::ZERO));
+    let reward = penalty.checked_mul(market_account.liquidation_reward_rate)?;
+    user_account.balance = user_account.balance.checked_sub(penalty)?;
+    user_account.profit = user_account.profit.checked_sub(penalty)?;
+    market_account.vault_insurance_balance = market_account
+        .vault_insurance_balance
+        .checked_add(penalty.checked_sub(reward)?)?;
+    msg!(
+        "liquidate position pl:{},penalty:{},reward:{}",
+        total_pl,
+        penalty,
+        reward
+    );
+    if reward.is_positive() {
+        let cpi_ctx: CpiContext<Transfer> = ctx.accounts.into();
+        let (_pda, bump_seed) =
+            Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
+        let seeds: &[&[u8]] = &[com::VAULT_TOKEN_AUTHORITY_SEED, &[bump_seed]];
+        token::transfer(cpi_ctx.with_signer(&[seeds]), reward.to_token_amount()?)?;
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LiquidatePosition<'info> {
+    #[account(mut)]
+    pub liquidator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [com::USER_ACCOUNT_SEED,position_account.authority.as_ref()],
+        bump,
+    )]
+    pub user_account: Box<Account<'info, user::UserAccount>>,
+    #[account(
+        mut,
+        constraint = market_account.key() == position_account.market_account.key()@BondError::AccountNumberNotMatch,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds=[com::POSITION_ACCOUNT_SEED,user_account.authority.as_ref(),user_account.key().as_ref(),position_account.position_seed_offset.to_string().as_bytes()],
+        bump,
+    )]
+    pub position_account: Box<Account<'info, position::Position>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
+    pub token_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+    )]
+    pub liquidator_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+        seeds = [com::VAULT_TOKEN_ACCOUNT_SEED],
+        bump,
+        )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: non check
+    #[account(
+        seeds = [com::VAULT_TOKEN_AUTHORITY_SEED],
+        bump,
+    )]
+    pub pda_authority_account: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> From<&mut LiquidatePosition<'info>> for CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+    fn from(accounts: &mut LiquidatePosition<'info>) -> Self {
+        let cpi_accounts = Transfer {
+            from: accounts.vault_token_account.to_account_info().clone(),
+            to: accounts.liquidator_token_account.to_account_info().clone(),
+            authority: accounts.pda_authority_account.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
+
+/// Force close the full positions of an account whose equity fell below the liquidation line.
+/// The remaining accounts start with `market_count` (market, pyth price, chainlink price) triples
+/// covering every market the account holds full positions in, followed by every open full position
+/// of the account, each exactly once. They are closed largest loss first until the account
+/// is back above the line, a position of a frozen market is never force closed.
+pub fn liquidate_account(ctx: Context<LiquidateAccount>, market_count: u8) -> Result<()> {
+    let accounts = &mut *ctx.accounts;
+    let market_accounts_len = market_count as usize * 3;
+    if ctx.remaining_accounts.len() < market_accounts_len {
+        return Err(BondError::AccountNumberNotMatch.into());
+    }
+    let (market_infos, position_infos) = ctx.remaining_accounts.split_at(market_accounts_len);
+    let position_keys: Vec<Pubkey> = position_infos.iter().map(|info| info.key()).collect();
+    check_account_positions(
+        &position_keys,
+        accounts.user_account.open_full_position_headers.len(),
+    )?;
+    let mut markets = load_full_position_markets(market_infos)?;
+    if is_account_healthy(&accounts.user_account, &markets)? {
+        return Err(BondError::PositionNotLiquidatable.into());
+    }
+    let mut positions = Vec::with_capacity(position_infos.len());
+    for account_info in position_infos.iter() {
+        if *account_info.owner != crate::id() || !account_info.is_writable {
+            return Err(BondError::AccountNumberNotMatch.into());
+        }
+        let position_account =
+            position::Position::try_deserialize(&mut &account_info.try_borrow_data()?[..])?;
+        if position_account.authority != accounts.user_account.authority
+            || position_account.position_type != position::PositionType::Full
+            || position_account.position_status != position::PositionStatus::Normal
+            || !accounts
+                .user_account
+                .open_full_position_headers
+                .iter()
+                .any(|h| h.position_seed_offset == position_account.position_seed_offset)
+        {
+            return Err(BondError::PositionStatusInvalid.into());
+        }
+        let index = markets
+            .iter()
+            .position(|m| *m.info.key == position_account.market_account)
+            .ok_or(BondError::IllegalMarketAccount)?;
+        let m = &markets[index];
+        let pl = equity::value_position(
+            &position_account.get_header(m.market.full_position_id),
+            &m.market,
+            &m.price,
+            Clock::get()?.unix_timestamp,
+        )?
+        .get_pl()?;
+        positions.push((pl, index, position_account, account_info));
+    }
+    // largest loss first
+    positions.sort_by_key(|(pl, _, _, _)| *pl);
+    let liquidator = accounts.liquidator.key();
+    let mut closed = 0;
+    let mut touched = vec![false; markets.len()];
+    for (pl, index, mut position_account, account_info) in positions {
+        if is_account_healthy(&accounts.user_account, &markets)? {
+            break;
+        }
+        if !accounts
+            .user_account
+            .open_full_position_headers
+            .iter()
+            .any(|h| h.position_seed_offset == position_account.position_seed_offset)
+        {
+            return Err(BondError::PositionStatusInvalid.into());
+        }
+        msg!(
+            "liquidate account position:{},pl:{}",
+            position_account.position_seed_offset,
+            pl
+        );
+        let m = &mut markets[index];
+        if m.market.status == market::MarketStatus::Frozen {
+            return Err(BondError::MarketFrozen.into());
+        }
+        settle_position(
+            &mut m.market,
+            &mut accounts.user_account,
+            &mut position_account,
+            &m.price,
+            position::PositionStatus::ForceClosing,
+            liquidator,
+        )?;
+        position_account.try_serialize(&mut &mut account_info.try_borrow_mut_data()?[..])?;
+        touched[index] = true;
+        closed += 1;
+    }
+    if closed == 0 {
+        return Err(BondError::PositionStatusInvalid.into());
+    }
+    for (m, _) in markets.iter().zip(touched).filter(|(_, t)| *t) {
+        m.save()?;
+    }
+    msg!("liquidate account closed:{}", closed);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct LiquidateAccount<'info> {
+    pub liquidator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [com::USER_ACCOUNT_SEED,user_account.authority.as_ref()],
+        bump,
+    )]
+    pub user_account: Box<Account<'info, user::UserAccount>>,
+}
+
+// every open full position must be passed once, so the caller cannot pick which ones are closed
+fn check_account_positions(position_keys: &[Pubkey], open_positions: usize) -> Result<()> {
+    for (i, key) in position_keys.iter().enumerate() {
+        if position_keys[..i].contains(key) {
+            return Err(BondError::DuplicatePositionAccount.into());
+        }
+    }
+    if position_keys.len() != open_positions {
+        return Err(BondError::AccountNumberNotMatch.into());
+    }
+    Ok(())
+}
+
+// the full position equity ratio is above the liquidation line
+fn is_account_healthy(
+    user_account: &user::UserAccount,
+    markets: &[FullPositionMarketAccount],
+) -> Result<bool> {
+    let margin = user_account.get_full_position_margin();
+    let equity = get_full_position_equity(user_account, markets)?;
+    msg!(
+        "liquidate account equity:{},margin:{}",
+        equity.get_equity()?,
+        margin
+    );
+    equity.is_above(margin, com::BURST_RATE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_account_positions() {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        assert!(check_account_positions(&[a, b], 2).is_ok());
+        assert_eq!(
+            check_account_positions(&[a, a], 2).unwrap_err(),
+            BondError::DuplicatePositionAccount.into()
+        );
+        assert_eq!(
+            check_account_positions(&[a, a], 1).unwrap_err(),
+            BondError::DuplicatePositionAccount.into()
+        );
+        // a subset of the open positions cannot be ordered against the others
+        assert_eq!(
+            check_account_positions(&[b], 2).unwrap_err(),
+            BondError::AccountNumberNotMatch.into()
+        );
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{
+    com,
+    decimal::Decimal,
+    errors::BondError,
+    state::{equity, market, position, user},
+};
+use anchor_lang::prelude::*;
+
+/// Move funds from the balance into the margin of an independent position,
+/// lowering its effective leverage and moving its liquidation price away.
+pub fn add_margin(ctx: Context<UpdateMargin>, amount: u64) -> Result<()> {
+    let amount = Decimal::from_raw_u64(amount)?;
+    let accounts = &mut *ctx.accounts;
+    check_margin_update(&accounts.market_account, &accounts.position_account, amount)?;
+    let user_account = &mut accounts.user_account;
+    let position_account = &mut accounts.position_account;
+    if user_account.balance < amount {
+        return Err(BondError::InsufficientBalanceForUser.into());
+    }
+    user_account.balance = user_account.balance.checked_sub(amount)?;
+    user_account.lock_margin(
+        &position_account.position_type,
+        position_account.direction,
+        amount,
+    )?;
+    position_account.margin = position_account.margin.checked_add(amount)?;
+    position_account.leverage = position_account.get_effective_leverage()?;
+    msg!(
+        "add margin:{},margin:{},leverage:{},liquidation price:{}",
+        amount,
+        position_account.margin,
+        position_account.leverage,
+        position_account.get_liquidation_price(accounts.market_account.risk_params.burst_rate)?
+    );
+    Ok(())
+}
+
+/// Move funds from the margin of an independent position back to the balance.
+/// The position must stay within the leverage limits and above the maintenance ratio.
+pub fn remove_margin(ctx: Context<UpdateMargin>, amount: u64) -> Result<()> {
+    let amount = Decimal::from_raw_u64(amount)?;
+    let accounts = &mut *ctx.accounts;
+    check_margin_update(&accounts.market_account, &accounts.position_account, amount)?;
+    let market_account = &mut accounts.market_account;
+    let user_account = &mut accounts.user_account;
+    let position_account = &mut accounts.position_account;
+    if amount >= position_account.margin {
+        return Err(BondError::InsufficientMargin.into());
+    }
+    let price = market_account.get_price(
+        &accounts.pyth_price_account,
+        &accounts.chianlink_price_account,
+    )?;
+    let now = Clock::get()?.unix_timestamp;
+    market_account.update_funding(now)?;
+    position_account.margin = position_account.margin.checked_sub(amount)?;
+    position_account.leverage = position_account.get_effective_leverage()?;
+    if position_account.leverage
+        > market_account.get_max_leverage(position_account.get_fund_size()?)
+    {
+        return Err(BondError::LeverageTooHigh.into());
+    }
+    let valuation = equity::value_position(
+        &position_account.get_header(market_account.full_position_id),
+        market_account,
+        &price,
+        now,
+    )?;
+    let position_equity = position_account.margin.checked_add(valuation.get_pl()?)?;
+    let burst_rate = market_account.risk_params.burst_rate;
+    if position_equity.checked_div(position_account.margin)? < burst_rate {
+        return Err(BondError::InsufficientMargin.into());
+    }
+    user_account.release_margin(
+        &position_account.position_type,
+        position_account.direction,
+        amount,
+    )?;
+    user_account.balance = user_account.balance.checked_add(amount)?;
+    msg!(
+        "remove margin:{},margin:{},leverage:{},liquidation price:{}",
+        amount,
+        position_account.margin,
+        position_account.leverage,
+        position_account.get_liquidation_price(market_account.risk_params.burst_rate)?
+    );
+    Ok(())
+}
+
+// only the margin of open independent positions can move
+fn check_margin_update(
+    market_account: &market::Market,
+    position_account: &position::Position,
+    amount: Decimal,
+) -> Result<()> {
+    if market_account.status == market::MarketStatus::Frozen {
+        return Err(BondError::MarketFrozen.into());
+    }
+    if position_account.position_status != position::PositionStatus::Normal {
+        return Err(BondError::PositionStatusInvalid.into());
+    }
+    if position_account.position_type != position::PositionType::Independent
+        || !amount.is_positive()
+    {
+        return Err(BondError::InvalidParameterOfPosition.into());
+    }
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateMargin<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority@BondError::UserTransactionAccountMismatch,
+        seeds = [com::USER_ACCOUNT_SEED,authority.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Box<Account<'info, user::UserAccount>>,
+    #[account(
+        mut,
+        constraint = market_account.key() == position_account.market_account.key()@BondError::AccountNumberNotMatch,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds=[com::POSITION_ACCOUNT_SEED,user_account.authority.as_ref(),user_account.key().as_ref(),position_account.position_seed_offset.to_string().as_bytes()],
+        bump,
+    )]
+    pub position_account: Box<Account<'info, position::Position>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+}
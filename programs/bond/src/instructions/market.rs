@@ -1,12 +1,14 @@
 use crate::com;
+use crate::decimal::Decimal;
 use crate::errors::BondError;
-use crate::state::market;
+use crate::state::{config, investor, market};
 pub(crate) use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
+use anchor_spl::token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer};
+use std::convert::TryFrom;
 pub fn initialize_market(
     ctx: Context<InitializeMarket>,
     pair: String,
-    spread: f64,
+    spread: u64,
     bump: u8,
     pyth_price_account: String,
     chianlink_price_account: String,
@@ -17,28 +19,34 @@ pub fn initialize_market(
     }
     market_account.pair = pair.clone();
     market_account.max_leverage = 125;
-    market_account.management_rate = 0.0004;
-    market_account.transaction_rate = 0.003;
-    market_account.insurance_rate = 0.0005;
-    market_account.margin_rate = 1.0;
+    market_account.management_rate = Decimal::new(4, 4);
+    market_account.transaction_rate = Decimal::new(3, 3);
+    market_account.insurance_rate = Decimal::new(5, 4);
+    market_account.margin_rate = Decimal::ONE;
     market_account.status = market::MarketStatus::Normal;
     market_account.vault_full = 0;
-    market_account.vault_base_balance = 0.0;
-    market_account.vault_profit_balance = 0.0;
-    market_account.vault_insurance_balance = 0.0;
-    market_account.long_position_total = 0.0;
-    market_account.short_position_total = 0.0;
+    market_account.vault_base_balance = Decimal::ZERO;
+    market_account.vault_profit_balance = Decimal::ZERO;
+    market_account.vault_insurance_balance = Decimal::ZERO;
+    market_account.long_position_total = Decimal::ZERO;
+    market_account.short_position_total = Decimal::ZERO;
+    market_account.long_size_total = Decimal::ZERO;
+    market_account.short_size_total = Decimal::ZERO;
     market_account.authority = ctx.accounts.initializer.key();
     market_account.operator = [ctx.accounts.initializer.key(); 5];
-    market_account.spread = spread;
+    market_account.spread = Decimal::from_raw_u64(spread)?;
     market_account.officer = false;
     market_account.is_support_full_position = false;
-    if ctx.accounts.initializer.key() == com::base_account::get_team_authority() {
+    market_account.max_price_divergence = Decimal::ZERO;
+    market_account.max_price_age = 60;
+    market_account.max_confidence_ratio = Decimal::new(2, 2);
+    market_account.liquidation_penalty_rate = Decimal::new(1, 2);
+    market_account.liquidation_reward_rate = Decimal::new(5, 1);
+    market_account.funding_update_time = Clock::get()?.unix_timestamp;
+    market_account.fee_project_share = Decimal::new(5, 1);
+    market_account.risk_params = market::RiskParams::default();
+    if ctx.accounts.initializer.key() == ctx.accounts.config_account.team_authority {
         market_account.officer = true;
-        let c = com::FullPositionMarket::from(pair.as_str());
-        if c != com::FullPositionMarket::None {
-            market_account.is_support_full_position = true;
-        }
     }
     market_account.pyth_price_account =
         Pubkey::try_from(pyth_price_account.as_str()).map_err(|err| {
@@ -54,10 +62,15 @@ pub fn initialize_market(
     Ok(ctx.accounts.market_account.key())
 }
 #[derive(Accounts)]
-#[instruction(pair: String,spread: f64,bump: u8)]
+#[instruction(pair: String,spread: u64,bump: u8)]
 pub struct InitializeMarket<'info> {
     #[account(mut)]
     pub initializer: Signer<'info>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
     #[account(
         init,
         payer=initializer,
@@ -69,12 +82,423 @@ pub struct InitializeMarket<'info> {
     system_program: Program<'info, System>,
 }
 
+pub fn update_oracle_config(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    max_price_divergence: u64,
+    max_price_age: i64,
+    max_confidence_ratio: u64,
+) -> Result<()> {
+    if max_price_age < 0 {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.max_price_divergence = Decimal::from_raw_u64(max_price_divergence)?;
+    market_account.max_price_age = max_price_age;
+    market_account.max_confidence_ratio = Decimal::from_raw_u64(max_confidence_ratio)?;
+    msg!(
+        "update oracle config pair:{:?},max_price_divergence:{},max_price_age:{},max_confidence_ratio:{}",
+        pair,
+        market_account.max_price_divergence,
+        market_account.max_price_age,
+        market_account.max_confidence_ratio
+    );
+    Ok(())
+}
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct UpdateMarketByAuthority<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority@BondError::NoPermission,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+}
+
+pub fn update_liquidation_config(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    liquidation_penalty_rate: u64,
+    liquidation_reward_rate: u64,
+) -> Result<()> {
+    let liquidation_penalty_rate = Decimal::from_raw_u64(liquidation_penalty_rate)?;
+    let liquidation_reward_rate = Decimal::from_raw_u64(liquidation_reward_rate)?;
+    if liquidation_penalty_rate > Decimal::ONE || liquidation_reward_rate > Decimal::ONE {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.liquidation_penalty_rate = liquidation_penalty_rate;
+    market_account.liquidation_reward_rate = liquidation_reward_rate;
+    msg!(
+        "update liquidation config pair:{:?},penalty_rate:{},reward_rate:{}",
+        pair,
+        liquidation_penalty_rate,
+        liquidation_reward_rate
+    );
+    Ok(())
+}
+
+/// Set the profit share boost paid to bonds per year of lock term
+pub fn update_bond_config(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    bond_boost_rate: u64,
+) -> Result<()> {
+    let bond_boost_rate = Decimal::from_raw_u64(bond_boost_rate)?;
+    if bond_boost_rate > Decimal::ONE {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    ctx.accounts.market_account.bond_boost_rate = bond_boost_rate;
+    msg!(
+        "update bond config pair:{:?},boost_rate:{}",
+        pair,
+        bond_boost_rate
+    );
+    Ok(())
+}
+
+/// Set the seconds a divestment request waits before it can be executed
+pub fn update_divestment_config(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    divestment_cooldown: i64,
+) -> Result<()> {
+    if divestment_cooldown < 0 {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    ctx.accounts.market_account.divestment_cooldown = divestment_cooldown;
+    msg!(
+        "update divestment config pair:{:?},cooldown:{}",
+        pair,
+        divestment_cooldown
+    );
+    Ok(())
+}
+
+pub fn update_fee_config(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    transaction_rate: u64,
+    management_rate: u64,
+    fee_project_share: u64,
+) -> Result<()> {
+    let transaction_rate = Decimal::from_raw_u64(transaction_rate)?;
+    let management_rate = Decimal::from_raw_u64(management_rate)?;
+    let fee_project_share = Decimal::from_raw_u64(fee_project_share)?;
+    if transaction_rate > Decimal::ONE
+        || management_rate > Decimal::ONE
+        || fee_project_share > Decimal::ONE
+    {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.transaction_rate = transaction_rate;
+    market_account.management_rate = management_rate;
+    market_account.fee_project_share = fee_project_share;
+    msg!(
+        "update fee config pair:{:?},transaction_rate:{},management_rate:{},project_share:{}",
+        pair,
+        transaction_rate,
+        management_rate,
+        fee_project_share
+    );
+    Ok(())
+}
+
+/// Transfer the fees owed to the project fund wallet out of the vault.
+pub fn collect_project_fees(ctx: Context<CollectProjectFees>, pair: String) -> Result<()> {
+    let amount = ctx.accounts.market_account.vault_project_fee_balance;
+    if !amount.is_positive() {
+        return Ok(());
+    }
+    let cpi_ctx: CpiContext<Transfer> = ctx.accounts.into();
+    let (_pda, bump_seed) =
+        Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
+    let seeds: &[&[u8]] = &[com::VAULT_TOKEN_AUTHORITY_SEED, &[bump_seed]];
+    token::transfer(cpi_ctx.with_signer(&[seeds]), amount.to_token_amount()?)?;
+    ctx.accounts.market_account.vault_project_fee_balance = Decimal::ZERO;
+    msg!("collect project fees pair:{:?},amount:{}", pair, amount);
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct CollectProjectFees<'info> {
+    #[account(
+        mut,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
+    pub token_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+        constraint=project_fund_token_account.owner == config_account.project_fund_wallet@BondError::NoPermission,
+    )]
+    pub project_fund_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+        seeds = [com::VAULT_TOKEN_ACCOUNT_SEED],
+        bump,
+    )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: non check
+    #[account(
+        seeds = [com::VAULT_TOKEN_AUTHORITY_SEED],
+        bump,
+    )]
+    pub pda_authority_account: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> From<&mut CollectProjectFees<'info>>
+    for CpiContext<'_, '_, '_, 'info, Transfer<'info>>
+{
+    fn from(accounts: &mut CollectProjectFees<'info>) -> Self {
+        let cpi_accounts = Transfer {
+            from: accounts.vault_token_account.to_account_info().clone(),
+            to: accounts
+                .project_fund_token_account
+                .to_account_info()
+                .clone(),
+            authority: accounts.pda_authority_account.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
+
+/// Update the trading parameters of the market, by the authority or an operator.
+pub fn update_market_params(
+    ctx: Context<UpdateMarketByOperator>,
+    pair: String,
+    spread: u64,
+    insurance_rate: u64,
+    max_leverage: u16,
+) -> Result<()> {
+    let spread = Decimal::from_raw_u64(spread)?;
+    let insurance_rate = Decimal::from_raw_u64(insurance_rate)?;
+    if insurance_rate > Decimal::ONE || max_leverage == 0 || max_leverage > com::MAX_LEVERAGE {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.spread = spread;
+    market_account.insurance_rate = insurance_rate;
+    market_account.max_leverage = max_leverage;
+    msg!(
+        "update market params pair:{:?},spread:{},insurance_rate:{},max_leverage:{}",
+        pair,
+        spread,
+        insurance_rate,
+        max_leverage
+    );
+    Ok(())
+}
+
+/// Set the market status, by the authority or an operator.
+/// Locked markets only allow closing, frozen markets allow neither opening nor closing.
+pub fn set_market_status(
+    ctx: Context<UpdateMarketByOperator>,
+    pair: String,
+    status: u8,
+) -> Result<()> {
+    let status = market::MarketStatus::try_from(status).map_err(|err| {
+        msg!("{:?}", err);
+        BondError::InvalidMarketParameter
+    })?;
+    msg!("set market status pair:{:?},status:{:?}", pair, status);
+    ctx.accounts.market_account.status = status;
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct UpdateMarketByOperator<'info> {
+    pub operator: Signer<'info>,
+    #[account(
+        mut,
+        constraint = market_account.is_operator(&operator.key())@BondError::NoPermission,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+}
+
+/// Replace the operators of the market, unused slots are the default pubkey.
+pub fn set_operators(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    operators: [Pubkey; 5],
+) -> Result<()> {
+    msg!("set operators pair:{:?},operators:{:?}", pair, operators);
+    ctx.accounts.market_account.operator = operators;
+    Ok(())
+}
+
+pub fn update_risk_params(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    risk_params: market::RiskParams,
+) -> Result<()> {
+    risk_params.check()?;
+    let market_account = &mut ctx.accounts.market_account;
+    // accrue the funding at the previous rate first
+    market_account.update_funding(Clock::get()?.unix_timestamp)?;
+    market_account.risk_params = risk_params;
+    msg!("update risk params pair:{:?},{:?}", pair, risk_params);
+    Ok(())
+}
+
+/// Replace the leverage tiers of the market.
+pub fn set_leverage_tiers(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    tiers: Vec<market::LeverageTier>,
+) -> Result<()> {
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.check_leverage_tiers(&tiers)?;
+    let mut leverage_tiers = [market::LeverageTier::default(); market::MAX_LEVERAGE_TIERS];
+    leverage_tiers[..tiers.len()].copy_from_slice(&tiers);
+    market_account.leverage_tiers = leverage_tiers;
+    msg!("set leverage tiers pair:{:?},tiers:{:?}", pair, tiers);
+    Ok(())
+}
+
+pub fn transfer_authority(
+    ctx: Context<UpdateMarketByAuthority>,
+    pair: String,
+    new_authority: Pubkey,
+) -> Result<()> {
+    if new_authority == Pubkey::default() {
+        return Err(BondError::InvalidMarketParameter.into());
+    }
+    msg!(
+        "transfer authority pair:{:?},from:{:?},to:{:?}",
+        pair,
+        ctx.accounts.market_account.authority,
+        new_authority
+    );
+    ctx.accounts.market_account.authority = new_authority;
+    Ok(())
+}
+
+/// Accrue the market funding up to now, callable by anyone.
+pub fn update_funding(ctx: Context<UpdateFunding>, pair: String) -> Result<()> {
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.update_funding(Clock::get()?.unix_timestamp)?;
+    msg!(
+        "update funding pair:{:?},long_index:{},short_index:{}",
+        pair,
+        market_account.long_funding_index,
+        market_account.short_funding_index
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct UpdateFunding<'info> {
+    #[account(
+        mut,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+}
+
+/// Create the LP share mint of the market, by the market authority.
+/// Shares use the decimals of the vault token and are minted by the vault authority PDA.
+pub fn initialize_lp_mint(ctx: Context<InitializeLpMint>, pair: String) -> Result<()> {
+    msg!(
+        "initialize lp mint pair:{:?},mint:{:?}",
+        pair,
+        ctx.accounts.lp_mint.key()
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct InitializeLpMint<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        has_one = authority@BondError::NoPermission,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        init,
+        payer=authority,
+        mint::decimals=token_mint.decimals,
+        mint::authority=pda_authority_account,
+        seeds = [com::LP_MINT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Account<'info, Mint>,
+    /// CHECK: non check
+    #[account(
+        seeds = [com::VAULT_TOKEN_AUTHORITY_SEED],
+        bump,
+    )]
+    pub pda_authority_account: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    pub token_program: Program<'info, Token>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+/// Provide liquidity to the market, shares are minted at the net asset value of the pool.
 pub fn investment(ctx: Context<Investment>, pair: String, amount: u64) -> Result<()> {
+    let amount_decimal = Decimal::from_token_amount(amount)?;
+    let market_account = &ctx.accounts.market_account;
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let nav = market_account.get_nav(&price)?;
+    let supply = Decimal::from_token_amount(ctx.accounts.lp_mint.supply)?;
+    let shares = ctx
+        .accounts
+        .market_account
+        .add_liquidity(amount, nav, supply)?;
     token::transfer(ctx.accounts.into(), amount)?;
-    let market_account = &mut ctx.accounts.market_account;
-    market_account.vault_full += amount;
-    market_account.vault_base_balance += amount as f64;
-    msg!("investment pair:{:?}", pair);
+    let cpi_ctx: CpiContext<MintTo> = ctx.accounts.into();
+    let (_pda, bump_seed) =
+        Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
+    let seeds: &[&[u8]] = &[com::VAULT_TOKEN_AUTHORITY_SEED, &[bump_seed]];
+    token::mint_to(cpi_ctx.with_signer(&[seeds]), shares.to_token_amount()?)?;
+
+    let market_account = &ctx.accounts.market_account;
+    let investor_account = &mut ctx.accounts.investor_account;
+    investor_account.authority = ctx.accounts.user.key();
+    investor_account.market_account = market_account.key();
+    investor_account.invest(amount_decimal, shares, Clock::get()?.unix_timestamp)?;
+    msg!(
+        "investment pair:{:?},amount:{},nav:{},shares:{}",
+        pair,
+        amount_decimal,
+        nav,
+        shares
+    );
     Ok(())
 }
 #[derive(Accounts)]
@@ -82,7 +506,12 @@ pub fn investment(ctx: Context<Investment>, pair: String, amount: u64) -> Result
 pub struct Investment<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
-    #[account(address=com::base_account::get_vault_mint())]
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
     pub token_mint: Account<'info, Mint>,
     #[account(
         mut,
@@ -102,7 +531,43 @@ pub struct Investment<'info> {
         bump,
     )]
     pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds = [com::LP_MINT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint=lp_mint,
+    )]
+    pub user_lp_token_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: non check
+    #[account(
+        seeds = [com::VAULT_TOKEN_AUTHORITY_SEED],
+        bump,
+    )]
+    pub pda_authority_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer=user,
+        space=investor::InvestorAccount::LEN + 8,
+        seeds = [com::INVESTOR_ACCOUNT_SEED,user.key().as_ref(),market_account.key().as_ref()],
+        bump,
+    )]
+    pub investor_account: Box<Account<'info, investor::InvestorAccount>>,
     pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
 }
 
 impl<'info> From<&mut Investment<'info>> for CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
@@ -117,36 +582,143 @@ impl<'info> From<&mut Investment<'info>> for CpiContext<'_, '_, '_, 'info, Trans
     }
 }
 
-pub fn divestment(ctx: Context<Divestment>, pair: String, amount: u64) -> Result<()> {
-    let cpi_ctx: CpiContext<Transfer> = ctx.accounts.into();
+impl<'info> From<&mut Investment<'info>> for CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
+    fn from(accounts: &mut Investment<'info>) -> Self {
+        let cpi_accounts = MintTo {
+            mint: accounts.lp_mint.to_account_info().clone(),
+            to: accounts.user_lp_token_account.to_account_info().clone(),
+            authority: accounts.pda_authority_account.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
 
-    let (_pda, bump_seed) =
-        Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
-    let seeds = &[&com::VAULT_TOKEN_AUTHORITY_SEED[..], &[bump_seed]];
-    token::transfer(cpi_ctx.with_signer(&[&seeds[..]]), amount)?;
+/// Request to withdraw `shares` of the market liquidity, executed by `execute_divestment`
+/// once the divestment cooldown of the market at request time has passed,
+/// and within `com::DIVESTMENT_EXECUTION_WINDOW` after that. A new request replaces the pending one.
+pub fn request_divestment(
+    ctx: Context<RequestDivestment>,
+    pair: String,
+    shares: u64,
+) -> Result<()> {
+    if ctx.accounts.user_lp_token_account.amount < shares {
+        return Err(BondError::InsufficientShares.into());
+    }
+    let shares = Decimal::from_token_amount(shares)?;
+    let now = Clock::get()?.unix_timestamp;
+    let investor_account = &mut ctx.accounts.investor_account;
+    investor_account.authority = ctx.accounts.user.key();
+    investor_account.market_account = ctx.accounts.market_account.key();
+    investor_account.request_divestment(
+        shares,
+        ctx.accounts.market_account.divestment_cooldown,
+        now,
+    )?;
+    let executable_time = investor_account.get_divestment_executable_time();
+    msg!(
+        "request divestment pair:{:?},shares:{},executable from:{},until:{}",
+        pair,
+        shares,
+        executable_time,
+        executable_time + com::DIVESTMENT_EXECUTION_WINDOW
+    );
+    Ok(())
+}
 
-    let market_account = &mut ctx.accounts.market_account;
-    if market_account.vault_full < amount || market_account.vault_base_balance < amount as f64 {
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct RequestDivestment<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        seeds = [com::LP_MINT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(
+        token::mint=lp_mint,
+        token::authority=user,
+    )]
+    pub user_lp_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + investor::InvestorAccount::LEN,
+        seeds = [com::INVESTOR_ACCOUNT_SEED,user.key().as_ref(),market_account.key().as_ref()],
+        bump,
+    )]
+    pub investor_account: Box<Account<'info, investor::InvestorAccount>>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Withdraw the liquidity requested by `request_divestment`,
+/// the shares are burned from the LP tokens held by the user for their part of the net asset value.
+/// The exposure of the market must stay within its limit of the remaining pool.
+pub fn execute_divestment(ctx: Context<ExecuteDivestment>, pair: String) -> Result<()> {
+    let market_account = &ctx.accounts.market_account;
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let nav = market_account.get_nav(&price)?;
+    let supply = Decimal::from_token_amount(ctx.accounts.lp_mint.supply)?;
+    let shares_decimal = ctx
+        .accounts
+        .investor_account
+        .take_pending_divestment(Clock::get()?.unix_timestamp)?;
+    let shares = shares_decimal.to_token_amount()?;
+    if ctx.accounts.user_lp_token_account.amount < shares {
+        return Err(BondError::InsufficientShares.into());
+    }
+    let payout = ctx
+        .accounts
+        .market_account
+        .remove_liquidity(shares_decimal, supply, nav)?;
+    ctx.accounts.market_account.check_exposure_limit()?;
+    let amount = payout.to_token_amount()?;
+    if ctx.accounts.vault_token_account.amount < amount {
         return Err(BondError::InsufficientVaultBalance.into());
     }
-    market_account.vault_full -= amount;
-    market_account.vault_base_balance -= amount as f64;
-    msg!("divestment pair:{:?}", pair);
-
+    token::burn(ctx.accounts.into(), shares)?;
+    let cpi_ctx: CpiContext<Transfer> = ctx.accounts.into();
+    let (_pda, bump_seed) =
+        Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
+    let seeds: &[&[u8]] = &[com::VAULT_TOKEN_AUTHORITY_SEED, &[bump_seed]];
+    token::transfer(cpi_ctx.with_signer(&[seeds]), amount)?;
+    ctx.accounts
+        .investor_account
+        .divest(shares_decimal, payout)?;
+    msg!(
+        "execute divestment pair:{:?},shares:{},nav:{},payout:{}",
+        pair,
+        shares_decimal,
+        nav,
+        payout
+    );
     Ok(())
 }
 
 #[derive(Accounts)]
-#[instruction(pair: String,amount:u64)]
-pub struct Divestment<'info> {
+#[instruction(pair: String)]
+pub struct ExecuteDivestment<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
-    #[account(address=com::base_account::get_vault_mint())]
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
     pub token_mint: Account<'info, Mint>,
     #[account(
         mut,
-        constraint=vault_token_account.owner != user.key()@BondError::NoPermission,
-        constraint=vault_token_account.amount >= amount@BondError::InsufficientVaultBalance,
+        token::mint=token_mint,
     )]
     pub user_token_account: Account<'info, TokenAccount>,
     #[account(
@@ -168,11 +740,51 @@ pub struct Divestment<'info> {
         bump,
     )]
     pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds = [com::LP_MINT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint=lp_mint,
+        token::authority=user,
+    )]
+    pub user_lp_token_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [com::INVESTOR_ACCOUNT_SEED,user.key().as_ref(),market_account.key().as_ref()],
+        bump,
+    )]
+    pub investor_account: Box<Account<'info, investor::InvestorAccount>>,
     pub token_program: Program<'info, Token>,
 }
 
-impl<'info> From<&mut Divestment<'info>> for CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
-    fn from(accounts: &mut Divestment<'info>) -> Self {
+impl<'info> From<&mut ExecuteDivestment<'info>> for CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+    fn from(accounts: &mut ExecuteDivestment<'info>) -> Self {
+        let cpi_accounts = Burn {
+            mint: accounts.lp_mint.to_account_info().clone(),
+            from: accounts.user_lp_token_account.to_account_info().clone(),
+            authority: accounts.user.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
+
+impl<'info> From<&mut ExecuteDivestment<'info>> for CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+    fn from(accounts: &mut ExecuteDivestment<'info>) -> Self {
         let cpi_accounts = Transfer {
             from: accounts.vault_token_account.to_account_info().clone(),
             to: accounts.user_token_account.to_account_info().clone(),
@@ -182,3 +794,49 @@ impl<'info> From<&mut Divestment<'info>> for CpiContext<'_, '_, '_, 'info, Trans
         CpiContext::new(cpi_program, cpi_accounts)
     }
 }
+
+/// Report the realised and unrealised yield of an investor account at the current pool value.
+pub fn get_investor_yield(
+    ctx: Context<GetInvestorYield>,
+    pair: String,
+) -> Result<investor::InvestorYield> {
+    let market_account = &ctx.accounts.market_account;
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let nav = market_account.get_nav(&price)?;
+    let supply = Decimal::from_token_amount(ctx.accounts.lp_mint.supply)?;
+    let investor_yield = ctx.accounts.investor_account.get_yield(nav, supply)?;
+    msg!("investor yield pair:{:?},{:?}", pair, investor_yield);
+    Ok(investor_yield)
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct GetInvestorYield<'info> {
+    #[account(
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        seeds = [com::LP_MINT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(
+        constraint = investor_account.market_account == market_account.key()@BondError::IllegalMarketAccount,
+    )]
+    pub investor_account: Box<Account<'info, investor::InvestorAccount>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+}
@@ -1,9 +1,21 @@
+pub use config::*;
+pub use liquidation::*;
+pub use margin::*;
 pub use market::*;
+pub use nft_bond::*;
+pub use order::*;
 pub use position::*;
+pub use trigger::*;
 pub use user::*;
 pub use vault::*;
 
+pub mod config;
+pub mod liquidation;
+pub mod margin;
 pub mod market;
+pub mod nft_bond;
+pub mod order;
 pub mod position;
+pub mod trigger;
 pub mod user;
 pub mod vault;
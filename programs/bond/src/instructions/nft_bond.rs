@@ -0,0 +1,392 @@
+use crate::{
+    com,
+    decimal::Decimal,
+    errors::BondError,
+    metadata,
+    state::{bond, config, market},
+};
+use anchor_lang::prelude::*;
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{
+    self, spl_token::instruction::AuthorityType, Burn, Mint, MintTo, SetAuthority, Token,
+    TokenAccount, Transfer,
+};
+
+/// Provide liquidity to the market through an NFT bond.
+/// The shares are minted at the pool net asset value like `investment` but held in escrow,
+/// and a one-of-one NFT with Metaplex metadata is minted to the investor.
+/// The bond can be redeemed by whoever holds the NFT once `lock_term` has passed,
+/// with a profit share boost that grows with the lock term.
+pub fn bond_investment(
+    ctx: Context<BondInvestment>,
+    pair: String,
+    amount: u64,
+    lock_term: i64,
+    uri: String,
+) -> Result<()> {
+    if lock_term <= 0 || lock_term > com::BOND_MAX_LOCK_TERM {
+        return Err(BondError::InvalidBondLockTerm.into());
+    }
+    if uri.len() > metadata::MAX_URI_LENGTH {
+        return Err(BondError::BondUriTooLong.into());
+    }
+    let amount_decimal = Decimal::from_token_amount(amount)?;
+    let market_account = &ctx.accounts.market_account;
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let nav = market_account.get_nav(&price)?;
+    let supply = Decimal::from_token_amount(ctx.accounts.lp_mint.supply)?;
+    let boost = bond::Bond::get_boost(market_account.bond_boost_rate, lock_term)?;
+    let shares = ctx
+        .accounts
+        .market_account
+        .add_liquidity(amount, nav, supply)?;
+
+    token::transfer(ctx.accounts.into(), amount)?;
+    let (_pda, bump_seed) =
+        Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
+    let seeds: &[&[u8]] = &[com::VAULT_TOKEN_AUTHORITY_SEED, &[bump_seed]];
+    let cpi_ctx: CpiContext<MintTo> = ctx.accounts.into();
+    token::mint_to(cpi_ctx.with_signer(&[seeds]), shares.to_token_amount()?)?;
+
+    // mint the one-of-one certificate, describe it and lock its supply
+    let accounts = &ctx.accounts;
+    let cpi_ctx = CpiContext::new(
+        accounts.token_program.to_account_info(),
+        MintTo {
+            mint: accounts.bond_mint.to_account_info(),
+            to: accounts.user_bond_token_account.to_account_info(),
+            authority: accounts.pda_authority_account.to_account_info(),
+        },
+    );
+    token::mint_to(cpi_ctx.with_signer(&[seeds]), 1)?;
+    metadata::create_metadata_account(
+        accounts.metadata_program.to_account_info(),
+        metadata::CreateMetadataAccount {
+            metadata: accounts.metadata_account.to_account_info(),
+            mint: accounts.bond_mint.to_account_info(),
+            mint_authority: accounts.pda_authority_account.to_account_info(),
+            payer: accounts.user.to_account_info(),
+            update_authority: accounts.pda_authority_account.to_account_info(),
+            system_program: accounts.system_program.to_account_info(),
+            rent: accounts.rent.to_account_info(),
+        },
+        format!("{}{}", pair, com::BOND_NAME_SUFFIX),
+        com::BOND_SYMBOL.to_string(),
+        uri,
+        &[seeds],
+    )?;
+    let cpi_ctx = CpiContext::new(
+        accounts.token_program.to_account_info(),
+        SetAuthority {
+            current_authority: accounts.pda_authority_account.to_account_info(),
+            account_or_mint: accounts.bond_mint.to_account_info(),
+        },
+    );
+    token::set_authority(
+        cpi_ctx.with_signer(&[seeds]),
+        AuthorityType::MintTokens,
+        None,
+    )?;
+
+    let market_account = &mut ctx.accounts.market_account;
+    market_account.bond_principal += amount;
+    let bond_account = &mut ctx.accounts.bond_account;
+    bond_account.mint = ctx.accounts.bond_mint.key();
+    bond_account.market_account = market_account.key();
+    bond_account.principal = amount_decimal;
+    bond_account.shares = shares;
+    bond_account.issue_time = Clock::get()?.unix_timestamp;
+    bond_account.lock_term = lock_term;
+    bond_account.boost = boost;
+    msg!(
+        "bond investment pair:{:?},bond:{:?},amount:{},nav:{},shares:{},lock_term:{},boost:{}",
+        pair,
+        bond_account.mint,
+        amount_decimal,
+        nav,
+        shares,
+        lock_term,
+        boost
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String,amount:u64)]
+pub struct BondInvestment<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
+    pub token_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        constraint=user_token_account.amount >= amount@BondError::InsufficientBalance,
+    )]
+    pub user_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+        seeds = [com::VAULT_TOKEN_ACCOUNT_SEED],
+        bump,
+        )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds = [com::LP_MINT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init_if_needed,
+        payer=user,
+        token::mint=lp_mint,
+        token::authority=pda_authority_account,
+        seeds = [com::BOND_ESCROW_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub bond_escrow_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer=user,
+        mint::decimals=0,
+        mint::authority=pda_authority_account,
+    )]
+    pub bond_mint: Box<Account<'info, Mint>>,
+    #[account(
+        init,
+        payer=user,
+        associated_token::mint=bond_mint,
+        associated_token::authority=user,
+    )]
+    pub user_bond_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        init,
+        payer=user,
+        space=bond::Bond::LEN + 8,
+        seeds = [com::BOND_ACCOUNT_SEED,bond_mint.key().as_ref()],
+        bump,
+    )]
+    pub bond_account: Box<Account<'info, bond::Bond>>,
+    /// CHECK: created by the metadata program
+    #[account(
+        mut,
+        seeds = [metadata::METADATA_SEED,metadata_program.key().as_ref(),bond_mint.key().as_ref()],
+        seeds::program = metadata_program.key(),
+        bump,
+    )]
+    pub metadata_account: AccountInfo<'info>,
+    /// CHECK: non check
+    #[account(
+        seeds = [com::VAULT_TOKEN_AUTHORITY_SEED],
+        bump,
+    )]
+    pub pda_authority_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+    /// CHECK: the metadata program id
+    #[account(address = metadata::get_program_id())]
+    pub metadata_program: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> From<&mut BondInvestment<'info>> for CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+    fn from(accounts: &mut BondInvestment<'info>) -> Self {
+        let cpi_accounts = Transfer {
+            from: accounts.user_token_account.to_account_info().clone(),
+            to: accounts.vault_token_account.to_account_info().clone(),
+            authority: accounts.user.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
+
+impl<'info> From<&mut BondInvestment<'info>> for CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
+    fn from(accounts: &mut BondInvestment<'info>) -> Self {
+        let cpi_accounts = MintTo {
+            mint: accounts.lp_mint.to_account_info().clone(),
+            to: accounts.bond_escrow_account.to_account_info().clone(),
+            authority: accounts.pda_authority_account.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
+
+/// Redeem an NFT bond after its lock term, by whoever holds the NFT.
+/// The escrowed shares are burned for their part of the net asset value, the boost adds
+/// its share of the profit from the profit and loss pool, and the NFT is burned.
+/// Like `execute_divestment`, the exposure of the market must stay within its limit once the boost is paid.
+pub fn bond_divestment(ctx: Context<BondDivestment>, pair: String) -> Result<()> {
+    let bond_account = &ctx.accounts.bond_account;
+    if bond_account.is_locked(Clock::get()?.unix_timestamp) {
+        return Err(BondError::BondLocked.into());
+    }
+    let market_account = &mut ctx.accounts.market_account;
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let nav = market_account.get_nav(&price)?;
+    let supply = Decimal::from_token_amount(ctx.accounts.lp_mint.supply)?;
+    let (payout, boost) = market_account.redeem_bond(bond_account, supply, nav)?;
+    let amount = payout.checked_add(boost)?.to_token_amount()?;
+    if ctx.accounts.vault_token_account.amount < amount {
+        return Err(BondError::InsufficientVaultBalance.into());
+    }
+    let shares = bond_account.shares.to_token_amount()?;
+    msg!(
+        "bond divestment pair:{:?},bond:{:?},shares:{},nav:{},payout:{},boost:{}",
+        pair,
+        bond_account.mint,
+        bond_account.shares,
+        nav,
+        payout,
+        boost
+    );
+
+    token::burn(ctx.accounts.into(), 1)?;
+    let (_pda, bump_seed) =
+        Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
+    let seeds: &[&[u8]] = &[com::VAULT_TOKEN_AUTHORITY_SEED, &[bump_seed]];
+    let accounts = &ctx.accounts;
+    let cpi_ctx = CpiContext::new(
+        accounts.token_program.to_account_info(),
+        Burn {
+            mint: accounts.lp_mint.to_account_info(),
+            from: accounts.bond_escrow_account.to_account_info(),
+            authority: accounts.pda_authority_account.to_account_info(),
+        },
+    );
+    token::burn(cpi_ctx.with_signer(&[seeds]), shares)?;
+    let cpi_ctx: CpiContext<Transfer> = ctx.accounts.into();
+    token::transfer(cpi_ctx.with_signer(&[seeds]), amount)?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct BondDivestment<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
+    pub token_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+    )]
+    pub holder_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+        seeds = [com::VAULT_TOKEN_ACCOUNT_SEED],
+        bump,
+        )]
+    pub vault_token_account: Box<Account<'info, TokenAccount>>,
+    /// CHECK: non check
+    #[account(
+        seeds = [com::VAULT_TOKEN_AUTHORITY_SEED],
+        bump,
+    )]
+    pub pda_authority_account: AccountInfo<'info>,
+    #[account(
+        mut,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds = [com::LP_MINT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub lp_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        seeds = [com::BOND_ESCROW_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub bond_escrow_account: Box<Account<'info, TokenAccount>>,
+    #[account(mut)]
+    pub bond_mint: Box<Account<'info, Mint>>,
+    #[account(
+        mut,
+        token::mint=bond_mint,
+        token::authority=holder,
+        constraint=holder_bond_token_account.amount == 1@BondError::NoPermission,
+    )]
+    pub holder_bond_token_account: Box<Account<'info, TokenAccount>>,
+    #[account(
+        mut,
+        close=holder,
+        has_one=market_account@BondError::IllegalMarketAccount,
+        seeds = [com::BOND_ACCOUNT_SEED,bond_mint.key().as_ref()],
+        bump,
+    )]
+    pub bond_account: Box<Account<'info, bond::Bond>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> From<&mut BondDivestment<'info>> for CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+    fn from(accounts: &mut BondDivestment<'info>) -> Self {
+        let cpi_accounts = Burn {
+            mint: accounts.bond_mint.to_account_info().clone(),
+            from: accounts.holder_bond_token_account.to_account_info().clone(),
+            authority: accounts.holder.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
+
+impl<'info> From<&mut BondDivestment<'info>> for CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+    fn from(accounts: &mut BondDivestment<'info>) -> Self {
+        let cpi_accounts = Transfer {
+            from: accounts.vault_token_account.to_account_info().clone(),
+            to: accounts.holder_token_account.to_account_info().clone(),
+            authority: accounts.pda_authority_account.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
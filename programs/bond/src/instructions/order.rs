@@ -0,0 +1,255 @@
+use crate::{
+    com,
+    decimal::Decimal,
+    errors::BondError,
+    instructions::position::*,
+    state::{market, position, user},
+};
+use anchor_lang::prelude::*;
+use std::convert::TryFrom;
+
+/// Place a limit order, a pending position opened by a keeper once the price reaches `open_price`.
+/// The margin is reserved from the balance until the order is filled, cancelled or expired.
+#[allow(clippy::too_many_arguments)]
+pub fn place_limit_order(
+    ctx: Context<PlaceLimitOrder>,
+    pair: String,
+    size: u64,
+    leverage: u16,
+    position_type: u8,
+    direction: u8,
+    open_price: u64,
+    validity_time: i64,
+) -> Result<()> {
+    // check parameter
+    let size = Decimal::from_raw_u64(size)?;
+    let open_price = Decimal::from_raw_u64(open_price)?;
+    if !size.is_positive() || !open_price.is_positive() {
+        return Err(BondError::InvalidParameterOfPosition.into());
+    }
+    if leverage == 0 || leverage > com::MAX_LEVERAGE {
+        return Err(BondError::InvalidParameterOfPosition.into());
+    }
+    let now = Clock::get()?.unix_timestamp;
+    if validity_time <= now {
+        return Err(BondError::InvalidParameterOfPosition.into());
+    }
+    let position_account = &mut ctx.accounts.position_account;
+    let market_account = &ctx.accounts.market_account;
+    let user_account = &mut ctx.accounts.user_account;
+    if market_account.status != market::MarketStatus::Normal {
+        return Err(BondError::MarketPauses.into());
+    }
+    position_account.position_type =
+        position::PositionType::try_from(position_type).map_err(|err| {
+            msg!("{:?}", err);
+            BondError::InvalidParameterOfPosition
+        })?;
+    if position_account.position_type == position::PositionType::Full
+        && !market_account.is_support_full_position
+    {
+        return Err(BondError::MarketNotSupportOpenPosition.into());
+    }
+    position_account.direction = position::Direction::try_from(direction).map_err(|err| {
+        msg!("{:?}", err);
+        BondError::InvalidParameterOfPosition
+    })?;
+    let margin = size
+        .checked_mul(open_price)?
+        .checked_div_int(leverage as i64)?
+        .checked_mul(market_account.margin_rate)?;
+    if leverage > market_account.get_max_leverage(size.checked_mul(open_price)?) {
+        return Err(BondError::LeverageTooHigh.into());
+    }
+    // reserve the margin
+    if user_account.balance < margin {
+        return Err(BondError::InsufficientBalanceForUser.into());
+    }
+    user_account.balance = user_account.balance.checked_sub(margin)?;
+    // set position data
+    position_account.position_seed_offset = user_account.position_seed_offset;
+    position_account.margin = margin;
+    position_account.leverage = leverage;
+    position_account.position_status = position::PositionStatus::Pending;
+    position_account.size = size;
+    position_account.lot = 1;
+    position_account.open_price = open_price;
+    position_account.create_time = now;
+    position_account.validity_time = validity_time;
+    position_account.authority = ctx.accounts.authority.key();
+    position_account.market_account = market_account.key();
+    // set user account data
+    let position_seed_offset = user_account.position_seed_offset;
+    user_account.update_index_by_open(position_seed_offset);
+    // this is next position offset number
+    user_account.position_seed_offset += 1;
+    msg!(
+        "place limit order by {:?},open_price:{},margin:{}",
+        pair,
+        open_price,
+        margin
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(pair:String)]
+pub struct PlaceLimitOrder<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority@BondError::UserTransactionAccountMismatch,
+        seeds = [com::USER_ACCOUNT_SEED,authority.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Box<Account<'info, user::UserAccount>>,
+    #[account(
+        constraint=market_account.pair == pair@BondError::IllegalMarketAccount,
+        seeds = [com::MARKET_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        init,
+        payer=authority,
+        space=position::Position::LEN+8,
+        seeds=[com::POSITION_ACCOUNT_SEED,authority.key().as_ref(),user_account.key().as_ref(),user_account.position_seed_offset.to_string().as_bytes()],
+        bump,
+    )]
+    pub position_account: Box<Account<'info, position::Position>>,
+    system_program: Program<'info, System>,
+}
+
+/// Cancel a pending order and give back the reserved margin.
+/// The owner can cancel at any time, anyone else only once the order has expired.
+pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
+    let user_account = &mut ctx.accounts.user_account;
+    let position_account = &mut ctx.accounts.position_account;
+    if position_account.position_status != position::PositionStatus::Pending {
+        return Err(BondError::PositionStatusInvalid.into());
+    }
+    let now = Clock::get()?.unix_timestamp;
+    let operator = ctx.accounts.operator.key();
+    position_account.position_status = if operator == position_account.authority {
+        position::PositionStatus::NormalClosing
+    } else if now > position_account.validity_time {
+        position::PositionStatus::ForceClosing
+    } else {
+        return Err(BondError::NoPermission.into());
+    };
+    user_account.balance = user_account.balance.checked_add(position_account.margin)?;
+    user_account.update_index_by_close(position_account.position_seed_offset);
+    position_account.close_time = now;
+    position_account.close_operator = operator;
+    msg!(
+        "cancel order:{},margin:{}",
+        position_account.position_seed_offset,
+        position_account.margin
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct CancelOrder<'info> {
+    pub operator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [com::USER_ACCOUNT_SEED,position_account.authority.as_ref()],
+        bump,
+    )]
+    pub user_account: Box<Account<'info, user::UserAccount>>,
+    #[account(
+        mut,
+        seeds=[com::POSITION_ACCOUNT_SEED,user_account.authority.as_ref(),user_account.key().as_ref(),position_account.position_seed_offset.to_string().as_bytes()],
+        bump,
+    )]
+    pub position_account: Box<Account<'info, position::Position>>,
+}
+
+/// Open a pending order at its price once the oracle price reaches it, callable by any keeper.
+pub fn fill_order(ctx: Context<FillOrder>) -> Result<()> {
+    let market_account = &mut ctx.accounts.market_account;
+    let user_account = &mut ctx.accounts.user_account;
+    let position_account = &mut ctx.accounts.position_account;
+    if market_account.status != market::MarketStatus::Normal {
+        return Err(BondError::MarketPauses.into());
+    }
+    if position_account.position_status != position::PositionStatus::Pending {
+        return Err(BondError::PositionStatusInvalid.into());
+    }
+    // the market may have left the full position registry since the order was placed
+    if position_account.position_type == position::PositionType::Full
+        && !market_account.is_support_full_position
+    {
+        return Err(BondError::MarketNotSupportOpenPosition.into());
+    }
+    let now = Clock::get()?.unix_timestamp;
+    if now > position_account.validity_time {
+        return Err(BondError::OrderExpired.into());
+    }
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let reached = match position_account.direction {
+        position::Direction::Buy => price.buy_price <= position_account.open_price,
+        position::Direction::Sell => price.sell_price >= position_account.open_price,
+    };
+    if !reached {
+        return Err(BondError::OrderPriceNotReached.into());
+    }
+    // the reserved margin goes back to the balance before being locked as position margin
+    user_account.balance = user_account.balance.checked_add(position_account.margin)?;
+    position_account.position_status = position::PositionStatus::Normal;
+    position_account.open_spread = price.spread;
+    position_account.open_real_price = price.real_price;
+    position_account.open_time = now;
+    position_account.open_operator = ctx.accounts.operator.key();
+    let mut full_position_markets = load_full_position_markets(ctx.remaining_accounts)?;
+    apply_open_position(
+        market_account,
+        user_account,
+        position_account,
+        &mut full_position_markets,
+    )?;
+    msg!(
+        "fill order:{},open_price:{},real_price:{}",
+        position_account.position_seed_offset,
+        position_account.open_price,
+        price.real_price
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct FillOrder<'info> {
+    pub operator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [com::USER_ACCOUNT_SEED,position_account.authority.as_ref()],
+        bump,
+    )]
+    pub user_account: Box<Account<'info, user::UserAccount>>,
+    #[account(
+        mut,
+        constraint = market_account.key() == position_account.market_account.key()@BondError::AccountNumberNotMatch,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds=[com::POSITION_ACCOUNT_SEED,user_account.authority.as_ref(),user_account.key().as_ref(),position_account.position_seed_offset.to_string().as_bytes()],
+        bump,
+    )]
+    pub position_account: Box<Account<'info, position::Position>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+}
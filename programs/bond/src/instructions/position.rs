@@ -1,7 +1,8 @@
 use crate::{
     com,
+    decimal::Decimal,
     errors::BondError,
-    state::{market, position, user},
+    state::{config, equity, market, position, user},
 };
 
 use anchor_lang::prelude::*;
@@ -10,13 +11,14 @@ use std::convert::TryFrom;
 pub fn open_position(
     ctx: Context<OpenPosition>,
     pair: String,
-    size: f64,
+    size: u64,
     leverage: u16,
     position_type: u8,
     direction: u8,
 ) -> Result<()> {
     // check parameter
-    if size <= 0.0 {
+    let size = Decimal::from_raw_u64(size)?;
+    if !size.is_positive() {
         return Err(BondError::InvalidParameterOfPosition.into());
     }
     if leverage <= 0 || leverage > com::MAX_LEVERAGE as u16 {
@@ -29,7 +31,6 @@ pub fn open_position(
     if market_account.status != market::MarketStatus::Normal {
         return Err(BondError::MarketPauses.into());
     }
-    let pre_exposure = market_account.get_exposure();
     // set position data
     position_account.position_type =
         position::PositionType::try_from(position_type).map_err(|err| {
@@ -57,13 +58,11 @@ pub fn open_position(
     )?;
     msg!("price:{:?}", price);
     let margin = match position_account.direction {
-        position::Direction::Buy => com::f64_round(
-            size as f64 * price.buy_price / leverage as f64 * market_account.margin_rate,
-        ),
-        position::Direction::Sell => com::f64_round(
-            size as f64 * price.sell_price / leverage as f64 * market_account.margin_rate,
-        ),
-    };
+        position::Direction::Buy => size.checked_mul(price.buy_price)?,
+        position::Direction::Sell => size.checked_mul(price.sell_price)?,
+    }
+    .checked_div_int(leverage as i64)?
+    .checked_mul(market_account.margin_rate)?;
     position_account.position_seed_offset = user_account.position_seed_offset;
     position_account.margin = margin;
     position_account.position_status = position::PositionStatus::Normal;
@@ -75,9 +74,9 @@ pub fn open_position(
         position::Direction::Buy => price.buy_price,
         position::Direction::Sell => price.sell_price,
     };
-    position_account.close_price = 0.0;
-    position_account.stop_surplus_price = 0.0;
-    position_account.stop_loss_price = 0.0;
+    position_account.close_price = Decimal::ZERO;
+    position_account.stop_surplus_price = Decimal::ZERO;
+    position_account.stop_loss_price = Decimal::ZERO;
     position_account.create_time = Clock::get().unwrap().unix_timestamp;
     position_account.open_time = Clock::get().unwrap().unix_timestamp;
     position_account.close_time = 0;
@@ -87,98 +86,132 @@ pub fn open_position(
     position_account.market_account = market_account.key();
     // --finish set position data
 
-    let fund_size = position_account.get_fund_size();
+    // set user account data
+    let position_seed_offset = user_account.position_seed_offset;
+    user_account.update_index_by_open(position_seed_offset);
+    // this is next position offset number
+    user_account.position_seed_offset += 1;
+    let mut full_position_markets = load_full_position_markets(ctx.remaining_accounts)?;
+    apply_open_position(
+        market_account,
+        user_account,
+        position_account,
+        &mut full_position_markets,
+    )?;
+    msg!("create position order by {:?}", pair);
+    Ok(())
+}
+
+/// Book an opened position: update the market totals, pay the insurance fund
+/// and the opening fee, lock the margin and run the risk checks.
+pub fn apply_open_position(
+    market_account: &mut market::Market,
+    user_account: &mut user::UserAccount,
+    position_account: &mut position::Position,
+    full_position_markets: &mut [FullPositionMarketAccount],
+) -> Result<()> {
+    market_account.update_funding(Clock::get()?.unix_timestamp)?;
+    position_account.funding_index = market_account.get_funding_index(position_account.direction);
+    let pre_exposure = market_account.get_exposure()?;
+    let margin = position_account.margin;
+    let fund_size = position_account.get_fund_size()?;
+    if position_account.leverage > market_account.get_max_leverage(fund_size) {
+        return Err(BondError::LeverageTooHigh.into());
+    }
     // set market data
     match position_account.direction {
         position::Direction::Buy => {
-            market_account.long_position_total += fund_size;
+            market_account.long_position_total =
+                market_account.long_position_total.checked_add(fund_size)?;
+            market_account.long_size_total = market_account
+                .long_size_total
+                .checked_add(position_account.size)?;
             user_account.position_full_vector += 1;
         }
         position::Direction::Sell => {
-            market_account.short_position_total += fund_size;
+            market_account.short_position_total =
+                market_account.short_position_total.checked_add(fund_size)?;
+            market_account.short_size_total = market_account
+                .short_size_total
+                .checked_add(position_account.size)?;
             user_account.position_full_vector -= 1;
         }
     };
     // Pay insurance fund
-    let insurance_fund = margin * market_account.insurance_rate;
-    market_account.vault_insurance_balance += insurance_fund;
-    user_account.balance -= insurance_fund;
+    let insurance_fund = margin.checked_mul(market_account.insurance_rate)?;
+    market_account.vault_insurance_balance = market_account
+        .vault_insurance_balance
+        .checked_add(insurance_fund)?;
+    user_account.balance = user_account.balance.checked_sub(insurance_fund)?;
+    // Pay the opening transaction fee
+    let open_fee = market_account.get_transaction_fee(fund_size)?;
+    market_account.distribute_fee(open_fee)?;
+    user_account.balance = user_account.balance.checked_sub(open_fee)?;
+    user_account.profit = user_account.profit.checked_sub(open_fee)?;
+    position_account.open_fee = open_fee;
     // set user account data
-    let position_seed_offset = user_account.position_seed_offset;
-    user_account.update_index_by_open(position_seed_offset);
-    user_account.add_position_header(position::PositionHeader {
-        position_seed_offset,
-        open_price: position_account.open_price,
-        direction: position_account.direction,
-        size,
-        margin,
-        market: com::FullPositionMarket::from(pair.as_str()),
-    })?;
-    // this is next position offset number
-    user_account.position_seed_offset += 1;
+    if position_account.position_type == position::PositionType::Full {
+        user_account
+            .add_position_header(position_account.get_header(market_account.full_position_id))?;
+    }
     // pay margin fund
-    user_account.margin_total += margin;
-    match position_account.position_type {
-        position::PositionType::Full => {
-            user_account.margin_full_total += margin;
-            match position_account.direction {
-                position::Direction::Buy => user_account.margin_full_buy_total += margin,
-                position::Direction::Sell => user_account.margin_full_sell_total += margin,
-            }
-        }
-        position::PositionType::Independent => {
-            if user_account.balance < margin {
-                return Err(BondError::InsufficientMargin.into());
-            }
-            user_account.margin_independent_total += margin;
-            user_account.balance -= margin;
-            match position_account.direction {
-                position::Direction::Buy => user_account.margin_independent_buy_total += margin,
-                position::Direction::Sell => user_account.margin_independent_sell_total += margin,
-            }
+    if position_account.position_type == position::PositionType::Independent {
+        if user_account.balance < margin {
+            return Err(BondError::InsufficientMargin.into());
         }
+        user_account.balance = user_account.balance.checked_sub(margin)?;
     }
-    if user_account.balance < 0.0 {
+    user_account.lock_margin(
+        &position_account.position_type,
+        position_account.direction,
+        margin,
+    )?;
+    if user_account.balance.is_negative() {
         return Err(BondError::InsufficientBalanceForUser.into());
     }
     // user_account.open_full_position_headers.len()
-    let exposure = market_account.get_exposure();
-    let total_liquidity = market_account.get_total_liquidity();
+    let exposure = market_account.get_exposure()?;
+    let total_liquidity = market_account.get_total_liquidity()?;
     let fund_pool = match position_account.direction {
         position::Direction::Buy => market_account.long_position_total,
         position::Direction::Sell => market_account.short_position_total,
     };
-    let margin_full_total = com::f64_round(
-        user_account
-            .margin_full_buy_total
-            .max(user_account.margin_full_sell_total),
-    );
+    let margin_full_total = user_account.get_full_position_margin();
 
     // Risk judgment
+    let exposure_limit =
+        total_liquidity.checked_mul(market_account.risk_params.position_diff_proportion)?;
     msg!(
-        "exposure: {},total_liquidity: {},pre_exposure: {:?},position_direction: {:?}",
+        "exposure: {},total_liquidity: {},pre_exposure: {},position_direction: {:?}",
         exposure,
-        total_liquidity * com::POSITION_DIFF_PROPORTION,
+        exposure_limit,
         pre_exposure,
         position_account.direction
     );
-    if exposure > total_liquidity * com::POSITION_DIFF_PROPORTION && pre_exposure <= exposure {
+    if exposure > exposure_limit && pre_exposure <= exposure {
         return Err(BondError::RiskControlBlockingExposure.into());
     }
 
-    let user_account_equity = get_equity(ctx)?;
+    refresh_full_position_market(
+        full_position_markets,
+        &position_account.market_account,
+        market_account,
+    );
     // check margin
-    if (user_account_equity / margin_full_total) < com::BURST_RATE {
+    if !get_full_position_equity(user_account, full_position_markets)?
+        .is_above(margin_full_total, com::BURST_RATE)?
+    {
         return Err(BondError::InsufficientMargin.into());
     }
 
-    if fund_size > total_liquidity * com::POSITION_PROPORTION_ONE {
+    if fund_size
+        > total_liquidity.checked_mul(market_account.risk_params.position_proportion_one)?
+    {
         return Err(BondError::RiskControlBlockingFundSize.into());
     }
-    if fund_pool > total_liquidity * com::POSITION_PROPORTION {
+    if fund_pool > total_liquidity.checked_mul(market_account.risk_params.position_proportion)? {
         return Err(BondError::RiskControlBlockingFundPool.into());
     }
-    msg!("create position order by {:?}", pair);
     Ok(())
 }
 #[derive(Accounts)]
@@ -216,54 +249,6 @@ pub struct OpenPosition<'info> {
     /// CHECK: Verify later
     #[account(constraint=market_account.chianlink_price_account.key()==chianlink_price_account.key()@BondError::InvalidPriceAccount)]
     pub chianlink_price_account: AccountInfo<'info>,
-    /// CHECK: Verify later
-    #[account(
-        constraint=market_account_btc.pair == com::FullPositionMarket::BtcUsd.to_string()@BondError::IllegalMarketAccount,
-        constraint=market_account_btc.officer == true@BondError::IllegalMarketAccount,
-    )]
-    pub market_account_btc: Box<Account<'info, market::Market>>,
-    /// CHECK: Verify later
-    #[account(
-        constraint=market_account_eth.pair == com::FullPositionMarket::EthUsd.to_string()@BondError::IllegalMarketAccount,
-        constraint=market_account_eth.officer == true@BondError::IllegalMarketAccount,
-    )]
-    pub market_account_eth: Box<Account<'info, market::Market>>,
-    /// CHECK: Verify later
-    #[account(
-        constraint=market_account_sol.pair == com::FullPositionMarket::SolUsd.to_string()@BondError::IllegalMarketAccount,
-        constraint=market_account_sol.officer == true@BondError::IllegalMarketAccount,
-    )]
-    pub market_account_sol: Box<Account<'info, market::Market>>,
-    /// CHECK: Verify later
-    #[account(
-            constraint = com::base_account::get_pyth_price_account_btc() == pyth_price_account_btc.key()@BondError::InvalidPriceAccount,
-        )]
-    pub pyth_price_account_btc: AccountInfo<'info>,
-    /// CHECK: Verify later
-    #[account(
-            constraint = com::base_account::get_pyth_price_account_eth() == pyth_price_account_eth.key()@BondError::InvalidPriceAccount,
-        )]
-    pub pyth_price_account_eth: AccountInfo<'info>,
-    /// CHECK: Verify later
-    #[account(
-            constraint = com::base_account::get_pyth_price_account_sol() == pyth_price_account_sol.key()@BondError::InvalidPriceAccount,
-        )]
-    pub pyth_price_account_sol: AccountInfo<'info>,
-    /// CHECK: Verify later
-    #[account(
-        constraint = com::base_account::get_chainlink_price_account_btc() == chainlink_price_account_btc.key()@BondError::InvalidPriceAccount,
-    )]
-    pub chainlink_price_account_btc: AccountInfo<'info>,
-    /// CHECK: Verify later
-    #[account(
-        constraint = com::base_account::get_chainlink_price_account_eth() == chainlink_price_account_eth.key()@BondError::InvalidPriceAccount,
-    )]
-    pub chainlink_price_account_eth: AccountInfo<'info>,
-    /// CHECK: Verify later
-    #[account(
-        constraint = com::base_account::get_chainlink_price_account_sol() == chainlink_price_account_sol.key()@BondError::InvalidPriceAccount,
-    )]
-    pub chainlink_price_account_sol: AccountInfo<'info>,
     system_program: Program<'info, System>,
 }
 
@@ -280,7 +265,8 @@ pub fn close_position(ctx: Context<ClosePosition>, _identity: u8) -> Result<()>
     }
     // check user
     let is_user_operator = user_account.authority == ctx.accounts.authority.key();
-    let is_robot_operator = com::base_account::get_clearing_robot() == ctx.accounts.authority.key();
+    let is_robot_operator =
+        ctx.accounts.config_account.clearing_robot == ctx.accounts.authority.key();
     if !is_user_operator && !is_robot_operator {
         return Err(BondError::NoPermission.into());
     }
@@ -288,114 +274,234 @@ pub fn close_position(ctx: Context<ClosePosition>, _identity: u8) -> Result<()>
         &ctx.accounts.pyth_price_account,
         &ctx.accounts.chianlink_price_account,
     )?;
-    // set position data
-    if is_user_operator {
-        position_account.position_status = position::PositionStatus::NormalClosing;
+    let status = if is_user_operator {
+        position::PositionStatus::NormalClosing
+    } else {
+        position::PositionStatus::ForceClosing
+    };
+    settle_position(
+        market_account,
+        user_account,
+        position_account,
+        &price,
+        status,
+        ctx.accounts.authority.key(),
+    )?;
+    msg!("close position success!");
+    Ok(())
+}
+
+/// Partially close a position: realise the P/L of `size` at the current price,
+/// release the proportional margin and keep the rest of the position open.
+pub fn reduce_position(ctx: Context<ClosePosition>, size: u64) -> Result<()> {
+    let size = Decimal::from_raw_u64(size)?;
+    let user_account = &mut ctx.accounts.user_account;
+    let market_account = &mut ctx.accounts.market_account;
+    let position_account = &mut ctx.accounts.position_account;
+    if market_account.status == market::MarketStatus::Frozen {
+        return Err(BondError::MarketFrozen.into());
     }
-    if is_robot_operator {
-        position_account.position_status = position::PositionStatus::ForceClosing;
+    if position_account.position_status != position::PositionStatus::Normal {
+        return Err(BondError::PositionStatusInvalid.into());
     }
-    let total_pl = position_account.get_pl_price(&price);
-    position_account.profit = total_pl;
-    position_account.close_price = match position_account.direction {
-        position::Direction::Buy => price.sell_price,
-        position::Direction::Sell => price.buy_price,
-    };
+    if user_account.authority != ctx.accounts.authority.key() {
+        return Err(BondError::NoPermission.into());
+    }
+    // the whole position is closed with close_position
+    if !size.is_positive() || size >= position_account.size {
+        return Err(BondError::InvalidParameterOfPosition.into());
+    }
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    let margin = position_account
+        .margin
+        .checked_mul_div(size, position_account.size)?;
+    let total_pl = realise_position(
+        market_account,
+        user_account,
+        position_account,
+        &price,
+        size,
+        margin,
+    )?;
+    position_account.size = position_account.size.checked_sub(size)?;
+    position_account.margin = position_account.margin.checked_sub(margin)?;
+    if position_account.position_type == position::PositionType::Full {
+        user_account
+            .update_position_header(position_account.get_header(market_account.full_position_id));
+    }
+    msg!(
+        "reduce position size:{},margin:{},pl:{},remaining size:{}",
+        size,
+        margin,
+        total_pl,
+        position_account.size
+    );
+    Ok(())
+}
+
+/// Close the whole position at `price` and update the user totals.
+/// Returns the P/L realised by the user.
+pub fn settle_position(
+    market_account: &mut market::Market,
+    user_account: &mut user::UserAccount,
+    position_account: &mut position::Position,
+    price: &market::Price,
+    status: position::PositionStatus,
+    operator: Pubkey,
+) -> Result<Decimal> {
+    let total_pl = realise_position(
+        market_account,
+        user_account,
+        position_account,
+        price,
+        position_account.size,
+        position_account.margin,
+    )?;
+    match position_account.direction {
+        position::Direction::Buy => user_account.position_full_vector -= 1,
+        position::Direction::Sell => user_account.position_full_vector += 1,
+    }
+    user_account.update_index_by_close(position_account.position_seed_offset);
+    user_account
+        .remove_position_header(position_account.get_header(market_account.full_position_id));
+    // set position data
+    position_account.position_status = status;
+    position_account.close_price = position_account.get_close_price(price);
     position_account.close_real_price = price.real_price;
     position_account.close_spread = price.spread;
-    position_account.close_time = Clock::get().unwrap().unix_timestamp;
-    position_account.close_operator = ctx.accounts.authority.key();
-
-    let fund_size = position_account.get_fund_size();
+    position_account.close_time = Clock::get()?.unix_timestamp;
+    position_account.close_operator = operator;
+    Ok(total_pl)
+}
 
+/// Realise `size` of the position at `price` with its share `margin` of the margin:
+/// realise the price P/L and funding, settle it against the market pools,
+/// charge the closing and management fees and release the margin.
+/// The P/L and fees add up on the position across partial closes.
+/// Returns the P/L realised by the user.
+fn realise_position(
+    market_account: &mut market::Market,
+    user_account: &mut user::UserAccount,
+    position_account: &mut position::Position,
+    price: &market::Price,
+    size: Decimal,
+    margin: Decimal,
+) -> Result<Decimal> {
+    let now = Clock::get()?.unix_timestamp;
+    market_account.update_funding(now)?;
+    let fund_size = position_account.get_fund_size_of(size)?;
+    let price_pl = position_account.get_pl_price_of(price, size)?;
+    let fund = market_account.get_position_fund(
+        position_account.direction,
+        fund_size,
+        position_account.funding_index,
+    )?;
+    let mut total_pl = price_pl.checked_add(fund)?;
+    msg!(
+        "settle position price pl:{},fund:{},margin:{}",
+        price_pl,
+        fund,
+        margin
+    );
+    // The loss is limited to the margin of an independent position or to the balance
+    // in full position mode, the rest is bad debt
+    let loss_limit = match position_account.position_type {
+        position::PositionType::Full => user_account.balance.max(Decimal::ZERO),
+        position::PositionType::Independent => margin,
+    };
+    let mut uncovered_loss = Decimal::ZERO;
+    if total_pl < loss_limit.checked_neg()? {
+        msg!("The user's margin is insufficient to cover the loss");
+        uncovered_loss = loss_limit.checked_neg()?.checked_sub(total_pl)?;
+        total_pl = loss_limit.checked_neg()?;
+    }
+    let settlement = market_account.settle_pl(total_pl, uncovered_loss)?;
+    if settlement.bad_debt.is_positive() {
+        msg!(
+            "settle position bad debt:{},insurance covered:{},socialised:{},unpaid:{}",
+            settlement.bad_debt,
+            settlement.insurance_covered,
+            settlement.socialised,
+            settlement.unpaid
+        );
+        emit!(market::BadDebtEvent {
+            market: position_account.market_account,
+            user: position_account.authority,
+            position_seed_offset: position_account.position_seed_offset,
+            bad_debt: settlement.bad_debt,
+            insurance_covered: settlement.insurance_covered,
+            socialised: settlement.socialised,
+            unpaid: settlement.unpaid,
+        });
+    }
+    let total_pl = settlement.pl;
     // position settlement
     match position_account.position_type {
-        position::PositionType::Full => {}
-        position::PositionType::Independent => {}
-    }
-
-    let full_level = market_account.vault_full as f64;
-    // Priority in settlement from profit and loss pool
-    // Whether the basic fund pool is full
-    if total_pl >= 0.0 {
-        market_account.vault_profit_balance = market_account.vault_profit_balance - total_pl;
-        if market_account.vault_profit_balance < 0.0 {
-            market_account.vault_base_balance =
-                market_account.vault_base_balance + market_account.vault_profit_balance;
-            market_account.vault_profit_balance = 0.0;
-        }
-    } else {
-        market_account.vault_base_balance = market_account.vault_base_balance + total_pl.abs();
-        let d = market_account.vault_base_balance - full_level;
-        if d > 0.0 {
-            market_account.vault_profit_balance += d;
-            market_account.vault_base_balance = full_level;
+        position::PositionType::Full => {
+            user_account.balance = user_account.balance.checked_add(total_pl)?;
         }
-    }
-    if position_account.position_type == position::PositionType::Independent {
-        let mut margin = position_account.margin;
-        margin += total_pl;
-        if margin > 0.0 {
-            user_account.balance += margin;
-        } else {
-            msg!("The user's initial margin is insufficient to cover the loss");
+        position::PositionType::Independent => {
+            user_account.balance = user_account
+                .balance
+                .checked_add(margin.checked_add(total_pl)?)?;
         }
-    } else {
-        user_account.balance += total_pl
     }
+    // Fees are charged on what is left to the user, they never create a debt
+    let close_fee = market_account.get_transaction_fee(fund_size)?;
+    let management_fee =
+        market_account.get_management_fee(fund_size, position_account.open_time, now)?;
+    let available = user_account.balance.max(Decimal::ZERO);
+    let close_fee = close_fee.min(available);
+    let management_fee = management_fee.min(available.checked_sub(close_fee)?);
+    let fee = close_fee.checked_add(management_fee)?;
+    market_account.distribute_fee(fee)?;
+    user_account.balance = user_account.balance.checked_sub(fee)?;
+    user_account.profit = user_account.profit.checked_sub(fee)?;
+    msg!(
+        "settle position close_fee:{},management_fee:{}",
+        close_fee,
+        management_fee
+    );
     match position_account.direction {
         position::Direction::Buy => {
-            market_account.long_position_total -= fund_size;
-            user_account.position_full_vector -= 1;
+            market_account.long_position_total =
+                market_account.long_position_total.checked_sub(fund_size)?;
+            market_account.long_size_total = market_account.long_size_total.checked_sub(size)?;
         }
         position::Direction::Sell => {
-            market_account.short_position_total -= fund_size;
-            user_account.position_full_vector -= 1;
+            market_account.short_position_total =
+                market_account.short_position_total.checked_sub(fund_size)?;
+            market_account.short_size_total = market_account.short_size_total.checked_sub(size)?;
         }
     }
     // set user account data
-    user_account.margin_total -= position_account.margin;
-    match position_account.position_type {
-        position::PositionType::Full => {
-            user_account.margin_full_total -= position_account.margin;
-            match position_account.direction {
-                position::Direction::Buy => {
-                    user_account.margin_full_buy_total -= position_account.margin
-                }
-                position::Direction::Sell => {
-                    user_account.margin_full_sell_total -= position_account.margin
-                }
-            }
-        }
-        position::PositionType::Independent => {
-            user_account.margin_independent_total -= position_account.margin;
-            match position_account.direction {
-                position::Direction::Buy => {
-                    user_account.margin_independent_buy_total -= position_account.margin
-                }
-                position::Direction::Sell => {
-                    user_account.margin_independent_sell_total -= position_account.margin
-                }
-            }
-        }
-    }
-    user_account.update_index_by_close(position_account.position_seed_offset);
-    user_account.remove_position_header(position::PositionHeader {
-        position_seed_offset: position_account.position_seed_offset,
-        open_price: position_account.open_price,
-        direction: position_account.direction,
-        size: position_account.size,
-        margin: position_account.margin,
-        market: com::FullPositionMarket::from(market_account.pair.as_str()),
-    });
-    msg!("close position success!");
-    Ok(())
+    user_account.release_margin(
+        &position_account.position_type,
+        position_account.direction,
+        margin,
+    )?;
+    user_account.profit = user_account.profit.checked_add(total_pl)?;
+    // set position data
+    position_account.profit = position_account.profit.checked_add(total_pl)?;
+    position_account.close_fee = position_account.close_fee.checked_add(close_fee)?;
+    position_account.management_fee = position_account
+        .management_fee
+        .checked_add(management_fee)?;
+    Ok(total_pl)
 }
 
 #[derive(Accounts)]
 pub struct ClosePosition<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
     #[account(
         mut,
         // has_one = authority@BondError::UserTransactionAccountMismatch,
@@ -426,58 +532,83 @@ pub struct ClosePosition<'info> {
     pub chianlink_price_account: AccountInfo<'info>,
 }
 
-// get the full position equity
-fn get_equity(ctx: Context<OpenPosition>) -> Result<f64> {
-    let account_balance = ctx.accounts.user_account.balance;
-    let total_pl = get_pl_price_all_full_position(ctx)?;
-    Ok(account_balance + total_pl)
+/// A market holding full positions and its current price, loaded from the remaining accounts.
+pub struct FullPositionMarketAccount<'a, 'info> {
+    pub info: &'a AccountInfo<'info>,
+    pub market: market::Market,
+    pub price: market::Price,
 }
 
-// Floating P/L
-pub fn get_pl_price_all_full_position(ctx: Context<OpenPosition>) -> Result<f64> {
-    let btc_price = ctx.accounts.market_account_btc.get_price(
-        &ctx.accounts.pyth_price_account_btc,
-        &ctx.accounts.chainlink_price_account_btc,
-    )?;
-    let eth_price = ctx.accounts.market_account_btc.get_price(
-        &ctx.accounts.pyth_price_account_eth,
-        &ctx.accounts.chainlink_price_account_eth,
-    )?;
-    let sol_price = ctx.accounts.market_account_btc.get_price(
-        &ctx.accounts.pyth_price_account_sol,
-        &ctx.accounts.chainlink_price_account_sol,
-    )?;
-    let headers = &ctx.accounts.user_account.open_full_position_headers;
-    let mut total_pl: f64 = 0.0;
-    for header in headers.iter() {
-        let profit_and_fund_rate: f64 = match header.market {
-            com::FullPositionMarket::BtcUsd => {
-                header.get_pl_price(&btc_price)
-                    + ctx
-                        .accounts
-                        .market_account_btc
-                        .get_position_fund(header.direction.clone(), header.get_fund_size())
-            }
+impl<'a, 'info> FullPositionMarketAccount<'a, 'info> {
+    /// Write the market back to its account
+    pub fn save(&self) -> Result<()> {
+        if !self.info.is_writable {
+            return Err(BondError::IllegalMarketAccount.into());
+        }
+        self.market
+            .try_serialize(&mut &mut self.info.try_borrow_mut_data()?[..])
+    }
+}
 
-            com::FullPositionMarket::EthUsd => {
-                header.get_pl_price(&eth_price)
-                    + ctx
-                        .accounts
-                        .market_account_eth
-                        .get_position_fund(header.direction.clone(), header.get_fund_size())
+/// Load the markets of the full positions, passed in the remaining accounts
+/// as (market, pyth price, chainlink price) triples, with the funding accrued up to now.
+/// Every market registered for full positions that the user holds positions in must be passed.
+pub fn load_full_position_markets<'a, 'info>(
+    accounts: &'a [AccountInfo<'info>],
+) -> Result<Vec<FullPositionMarketAccount<'a, 'info>>> {
+    let chunks = accounts.chunks_exact(3);
+    if !chunks.remainder().is_empty() {
+        return Err(BondError::AccountNumberNotMatch.into());
+    }
+    let now = Clock::get()?.unix_timestamp;
+    chunks
+        .map(|chunk| {
+            let info = &chunk[0];
+            if *info.owner != crate::id() {
+                return Err(BondError::IllegalMarketAccount.into());
             }
-
-            com::FullPositionMarket::SolUsd => {
-                header.get_pl_price(&sol_price)
-                    + ctx
-                        .accounts
-                        .market_account_sol
-                        .get_position_fund(header.direction.clone(), header.get_fund_size())
+            let mut market = market::Market::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+            if market.full_position_id == 0 {
+                return Err(BondError::InvalidFullPositionMarket.into());
+            }
+            if market.pyth_price_account != chunk[1].key()
+                || market.chianlink_price_account != chunk[2].key()
+            {
+                return Err(BondError::InvalidPriceAccount.into());
             }
+            let price = market.get_price(&chunk[1], &chunk[2])?;
+            market.update_funding(now)?;
+            Ok(FullPositionMarketAccount {
+                info,
+                market,
+                price,
+            })
+        })
+        .collect()
+}
 
-            _ => 0.0,
-        };
-        total_pl += profit_and_fund_rate
+/// Replace the loaded copy of a market that the instruction has already updated
+pub fn refresh_full_position_market(
+    markets: &mut [FullPositionMarketAccount],
+    key: &Pubkey,
+    market_account: &market::Market,
+) {
+    for m in markets.iter_mut().filter(|m| m.info.key == key) {
+        m.market = market_account.clone();
     }
-    Ok(total_pl)
+}
+
+/// Value the full positions of the user, each with the market and price it belongs to
+pub fn get_full_position_equity(
+    user_account: &user::UserAccount,
+    markets: &[FullPositionMarketAccount],
+) -> Result<equity::Equity> {
+    let pairs: Vec<(&market::Market, &market::Price)> =
+        markets.iter().map(|m| (&m.market, &m.price)).collect();
+    equity::evaluate_full_positions(
+        user_account.balance,
+        &user_account.open_full_position_headers,
+        &pairs,
+        Clock::get()?.unix_timestamp,
+    )
 }
@@ -0,0 +1,124 @@
+use crate::{
+    com,
+    decimal::Decimal,
+    errors::BondError,
+    instructions::position::*,
+    state::{market, position, user},
+};
+use anchor_lang::prelude::*;
+
+/// Set the stop surplus and stop loss prices of a position, 0 clears a trigger.
+pub fn set_position_triggers(
+    ctx: Context<SetPositionTriggers>,
+    stop_surplus_price: u64,
+    stop_loss_price: u64,
+) -> Result<()> {
+    let market_account = &ctx.accounts.market_account;
+    let position_account = &mut ctx.accounts.position_account;
+    if position_account.position_status != position::PositionStatus::Normal {
+        return Err(BondError::PositionStatusInvalid.into());
+    }
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    position_account.stop_surplus_price = Decimal::from_raw_u64(stop_surplus_price)?;
+    position_account.stop_loss_price = Decimal::from_raw_u64(stop_loss_price)?;
+    position_account.check_triggers(&price)?;
+    msg!(
+        "set position triggers stop_surplus_price:{},stop_loss_price:{}",
+        position_account.stop_surplus_price,
+        position_account.stop_loss_price
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct SetPositionTriggers<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        constraint = market_account.key() == position_account.market_account.key()@BondError::AccountNumberNotMatch,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        has_one = authority@BondError::UserTransactionAccountMismatch,
+    )]
+    pub position_account: Box<Account<'info, position::Position>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+}
+
+/// Close a position at market once the price crossed one of its triggers, callable by anyone.
+pub fn execute_trigger(ctx: Context<ExecuteTrigger>) -> Result<()> {
+    let market_account = &mut ctx.accounts.market_account;
+    let user_account = &mut ctx.accounts.user_account;
+    let position_account = &mut ctx.accounts.position_account;
+    if market_account.status == market::MarketStatus::Frozen {
+        return Err(BondError::MarketFrozen.into());
+    }
+    if position_account.position_status != position::PositionStatus::Normal {
+        return Err(BondError::PositionStatusInvalid.into());
+    }
+    let price = market_account.get_price(
+        &ctx.accounts.pyth_price_account,
+        &ctx.accounts.chianlink_price_account,
+    )?;
+    if !position_account.is_trigger_reached(&price) {
+        return Err(BondError::TriggerNotReached.into());
+    }
+    let pl = settle_position(
+        market_account,
+        user_account,
+        position_account,
+        &price,
+        position::PositionStatus::NormalClosing,
+        ctx.accounts.operator.key(),
+    )?;
+    msg!(
+        "execute trigger close_price:{},pl:{}",
+        position_account.close_price,
+        pl
+    );
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ExecuteTrigger<'info> {
+    pub operator: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [com::USER_ACCOUNT_SEED,position_account.authority.as_ref()],
+        bump,
+    )]
+    pub user_account: Box<Account<'info, user::UserAccount>>,
+    #[account(
+        mut,
+        constraint = market_account.key() == position_account.market_account.key()@BondError::AccountNumberNotMatch,
+    )]
+    pub market_account: Box<Account<'info, market::Market>>,
+    #[account(
+        mut,
+        seeds=[com::POSITION_ACCOUNT_SEED,user_account.authority.as_ref(),user_account.key().as_ref(),position_account.position_seed_offset.to_string().as_bytes()],
+        bump,
+    )]
+    pub position_account: Box<Account<'info, position::Position>>,
+    /// CHECK: Verify later
+    #[account(
+        constraint = market_account.pyth_price_account.key() == pyth_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub pyth_price_account: AccountInfo<'info>,
+    /// CHECK: Verify later
+    #[account(
+        constraint=market_account.chianlink_price_account.key() == chianlink_price_account.key()@BondError::InvalidPriceAccount)
+    ]
+    pub chianlink_price_account: AccountInfo<'info>,
+}
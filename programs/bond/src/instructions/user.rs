@@ -1,5 +1,8 @@
 use crate::com;
+use crate::decimal::Decimal;
 use crate::errors::BondError;
+use crate::instructions::position::*;
+use crate::state::config;
 use crate::state::user::*;
 use anchor_lang::prelude::*;
 use anchor_spl::token::{self, Mint, Token, TokenAccount, Transfer};
@@ -9,11 +12,11 @@ pub fn initialize_user_account(ctx: Context<InitUserAccount>, bump: u8) -> Resul
     account.authority = ctx.accounts.initializer.key();
     // Reserve the next order number forever
     account.position_seed_offset = 1;
-    account.balance = 0.0;
-    account.profit = 0.0;
-    account.margin_total = 0.0;
-    account.margin_full_total = 0.0;
-    account.margin_independent_total = 0.0;
+    account.balance = Decimal::ZERO;
+    account.profit = Decimal::ZERO;
+    account.margin_total = Decimal::ZERO;
+    account.margin_full_total = Decimal::ZERO;
+    account.margin_independent_total = Decimal::ZERO;
     msg!("user account bump:{}", bump);
     Ok(ctx.accounts.user_account.key())
 }
@@ -37,8 +40,8 @@ pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
     // transfer
     token::transfer(ctx.accounts.into(), amount)?;
     let user_account = &mut ctx.accounts.user_account;
-    let balance = amount as f64;
-    user_account.balance += balance;
+    let balance = Decimal::from_token_amount(amount)?;
+    user_account.balance = user_account.balance.checked_add(balance)?;
     Ok(())
 }
 
@@ -47,7 +50,12 @@ pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
 pub struct Deposit<'info> {
     #[account(mut)]
     pub authority: Signer<'info>,
-    #[account(address=com::base_account::get_vault_mint())]
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
     pub token_mint: Account<'info, Mint>,
     #[account(
         mut,
@@ -82,3 +90,96 @@ impl<'info> From<&mut Deposit<'info>> for CpiContext<'_, '_, '_, 'info, Transfer
         CpiContext::new(cpi_program, cpi_accounts)
     }
 }
+
+pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+    let amount_decimal = Decimal::from_token_amount(amount)?;
+    let user_account = &ctx.accounts.user_account;
+    let full_position_markets = load_full_position_markets(ctx.remaining_accounts)?;
+    let total_pl = get_full_position_equity(user_account, &full_position_markets)?.total_pl;
+    // Unrealised profit of full positions can not be withdrawn, losses reduce the free balance
+    let free_balance = user_account
+        .balance
+        .checked_sub(user_account.margin_full_total)?
+        .checked_add(total_pl.min(Decimal::ZERO))?;
+    msg!(
+        "withdraw amount:{},balance:{},free balance:{}",
+        amount_decimal,
+        user_account.balance,
+        free_balance
+    );
+    if free_balance < amount_decimal {
+        return Err(BondError::InsufficientFreeBalance.into());
+    }
+    // The equity left must stay above the liquidation line
+    let equity = user_account
+        .balance
+        .checked_add(total_pl)?
+        .checked_sub(amount_decimal)?;
+    let margin_full_total = user_account.get_full_position_margin();
+    if margin_full_total.is_positive() && equity.checked_div(margin_full_total)? < com::BURST_RATE {
+        return Err(BondError::InsufficientMargin.into());
+    }
+
+    let cpi_ctx: CpiContext<Transfer> = ctx.accounts.into();
+    let (_pda, bump_seed) =
+        Pubkey::find_program_address(&[com::VAULT_TOKEN_AUTHORITY_SEED], ctx.program_id);
+    let seeds: &[&[u8]] = &[com::VAULT_TOKEN_AUTHORITY_SEED, &[bump_seed]];
+    token::transfer(cpi_ctx.with_signer(&[seeds]), amount)?;
+
+    let user_account = &mut ctx.accounts.user_account;
+    user_account.balance = user_account.balance.checked_sub(amount_decimal)?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+#[instruction(amount:u64)]
+pub struct Withdraw<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
+    #[account(address=config_account.vault_mint)]
+    pub token_mint: Account<'info, Mint>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        has_one = authority@BondError::UserTransactionAccountMismatch,
+        seeds = [com::USER_ACCOUNT_SEED,authority.key().as_ref()],
+        bump,
+    )]
+    pub user_account: Account<'info, UserAccount>,
+    #[account(
+        mut,
+        token::mint=token_mint,
+        constraint=vault_token_account.amount >= amount@BondError::InsufficientVaultBalance,
+        seeds = [com::VAULT_TOKEN_ACCOUNT_SEED],
+        bump,
+        )]
+    pub vault_token_account: Account<'info, TokenAccount>,
+    /// CHECK: non check
+    #[account(
+        seeds = [com::VAULT_TOKEN_AUTHORITY_SEED],
+        bump,
+    )]
+    pub pda_authority_account: AccountInfo<'info>,
+    token_program: Program<'info, Token>,
+}
+
+impl<'info> From<&mut Withdraw<'info>> for CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
+    fn from(accounts: &mut Withdraw<'info>) -> Self {
+        let cpi_accounts = Transfer {
+            from: accounts.vault_token_account.to_account_info().clone(),
+            to: accounts.user_token_account.to_account_info().clone(),
+            authority: accounts.pda_authority_account.to_account_info().clone(),
+        };
+        let cpi_program = accounts.token_program.to_account_info().clone();
+        CpiContext::new(cpi_program, cpi_accounts)
+    }
+}
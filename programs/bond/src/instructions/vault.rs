@@ -1,4 +1,6 @@
 use crate::com;
+use crate::errors::BondError;
+use crate::state::config;
 use anchor_lang::prelude::*;
 use anchor_spl::token::{
     self, spl_token::instruction::AuthorityType, Mint, SetAuthority, Token, TokenAccount,
@@ -27,9 +29,14 @@ pub fn initialize_vault(ctx: Context<InitializeVault>, bump: u8) -> Result<Pubke
 #[derive(Accounts)]
 #[instruction(bump: u8)]
 pub struct InitializeVault<'info> {
+    #[account(
+        seeds = [com::CONFIG_ACCOUNT_SEED],
+        bump,
+    )]
+    pub config_account: Box<Account<'info, config::Config>>,
     #[account(
         mut,
-        address=com::base_account::get_team_authority()
+        address=config_account.team_authority@BondError::NoPermission,
     )]
     pub initializer: Signer<'info>,
     #[account(
@@ -41,7 +48,7 @@ pub struct InitializeVault<'info> {
         token::authority=initializer,
     )]
     pub vault_account: Account<'info, TokenAccount>,
-    #[account(address=com::base_account::get_vault_mint())]
+    #[account(address=config_account.vault_mint)]
     pub token_mint: Account<'info, Mint>,
     pub system_program: Program<'info, System>,
     pub token_program: Program<'info, Token>,
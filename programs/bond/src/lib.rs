@@ -1,23 +1,57 @@
 use anchor_lang::prelude::*;
 use instructions::*;
+use state::config::ConfigParams;
+use state::investor::InvestorYield;
+use state::market::{LeverageTier, RiskParams};
 declare_id!("FXUEM9ZfqeWkAtHDCoCGB7C9cwNW1JcyhXB47i9J6B37");
 pub mod com;
+pub mod decimal;
 pub mod errors;
 pub mod instructions;
+pub mod metadata;
 pub mod price;
 pub mod state;
 #[program]
 pub mod bond {
     use super::*;
+    /// Create the protocol config, by the program upgrade authority
+    pub fn initialize_config(ctx: Context<InitializeConfig>, params: ConfigParams) -> Result<()> {
+        config::initialize_config(ctx, params)
+    }
+    /// Update the protocol config, by the governance authority
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        config::update_config(ctx, params)
+    }
+    /// Hand the config governance over to a new authority
+    pub fn transfer_config_authority(
+        ctx: Context<UpdateConfig>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        config::transfer_config_authority(ctx, new_authority)
+    }
+    /// Register a market for full positions, by the governance authority
+    pub fn add_full_position_market(
+        ctx: Context<UpdateFullPositionMarket>,
+        pair: String,
+    ) -> Result<()> {
+        config::add_full_position_market(ctx, pair)
+    }
+    /// Stop full positions on a market, by the governance authority
+    pub fn remove_full_position_market(
+        ctx: Context<UpdateFullPositionMarket>,
+        pair: String,
+    ) -> Result<()> {
+        config::remove_full_position_market(ctx, pair)
+    }
     /// Generate system vault account
     pub fn initialize_vault(ctx: Context<InitializeVault>, bump: u8) -> Result<Pubkey> {
         vault::initialize_vault(ctx, bump)
     }
-    /// create market
+    /// create market, `spread` is a fixed-point value with 6 decimal places
     pub fn initialize_market(
         ctx: Context<InitializeMarket>,
         pair: String,
-        spread: f64,
+        spread: u64,
         bump: u8,
         pyth_price_account: String,
         chianlink_price_account: String,
@@ -31,16 +65,141 @@ pub mod bond {
             chianlink_price_account,
         )
     }
+    /// Set the oracle checks of a market, a zero value disables the corresponding check
+    pub fn update_oracle_config(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        max_price_divergence: u64,
+        max_price_age: i64,
+        max_confidence_ratio: u64,
+    ) -> Result<()> {
+        market::update_oracle_config(
+            ctx,
+            pair,
+            max_price_divergence,
+            max_price_age,
+            max_confidence_ratio,
+        )
+    }
+    /// Set the liquidation penalty rate and the liquidator's share of it
+    pub fn update_liquidation_config(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        liquidation_penalty_rate: u64,
+        liquidation_reward_rate: u64,
+    ) -> Result<()> {
+        market::update_liquidation_config(
+            ctx,
+            pair,
+            liquidation_penalty_rate,
+            liquidation_reward_rate,
+        )
+    }
+    /// Set the profit share boost paid to bonds per year of lock term
+    pub fn update_bond_config(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        bond_boost_rate: u64,
+    ) -> Result<()> {
+        market::update_bond_config(ctx, pair, bond_boost_rate)
+    }
+    /// Set the cooldown between a divestment request and its execution
+    pub fn update_divestment_config(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        divestment_cooldown: i64,
+    ) -> Result<()> {
+        market::update_divestment_config(ctx, pair, divestment_cooldown)
+    }
+    /// Set the transaction and management rates and the project share of the fees
+    pub fn update_fee_config(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        transaction_rate: u64,
+        management_rate: u64,
+        fee_project_share: u64,
+    ) -> Result<()> {
+        market::update_fee_config(
+            ctx,
+            pair,
+            transaction_rate,
+            management_rate,
+            fee_project_share,
+        )
+    }
+    /// Transfer the collected project fees to the project fund wallet
+    pub fn collect_project_fees(ctx: Context<CollectProjectFees>, pair: String) -> Result<()> {
+        market::collect_project_fees(ctx, pair)
+    }
+    /// Update spread, insurance rate and max leverage
+    pub fn update_market_params(
+        ctx: Context<UpdateMarketByOperator>,
+        pair: String,
+        spread: u64,
+        insurance_rate: u64,
+        max_leverage: u16,
+    ) -> Result<()> {
+        market::update_market_params(ctx, pair, spread, insurance_rate, max_leverage)
+    }
+    /// Set the market status: 1 normal, 2 locked, 3 frozen
+    pub fn set_market_status(
+        ctx: Context<UpdateMarketByOperator>,
+        pair: String,
+        status: u8,
+    ) -> Result<()> {
+        market::set_market_status(ctx, pair, status)
+    }
+    /// Replace the market operators
+    pub fn set_operators(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        operators: [Pubkey; 5],
+    ) -> Result<()> {
+        market::set_operators(ctx, pair, operators)
+    }
+    /// Update the risk-control thresholds of the market
+    pub fn update_risk_params(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        risk_params: RiskParams,
+    ) -> Result<()> {
+        market::update_risk_params(ctx, pair, risk_params)
+    }
+    /// Replace the leverage tiers of the market
+    pub fn set_leverage_tiers(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        tiers: Vec<LeverageTier>,
+    ) -> Result<()> {
+        market::set_leverage_tiers(ctx, pair, tiers)
+    }
+    /// Hand the market over to a new authority
+    pub fn transfer_authority(
+        ctx: Context<UpdateMarketByAuthority>,
+        pair: String,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        market::transfer_authority(ctx, pair, new_authority)
+    }
+    /// Accrue the market funding indexes, callable by anyone
+    pub fn update_funding(ctx: Context<UpdateFunding>, pair: String) -> Result<()> {
+        market::update_funding(ctx, pair)
+    }
     pub fn initialize_user_account(ctx: Context<InitUserAccount>, bump: u8) -> Result<Pubkey> {
         user::initialize_user_account(ctx, bump)
     }
     pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
         user::deposit(ctx, amount)
     }
+    /// Withdraw free balance from the user account back to the user's token account
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        user::withdraw(ctx, amount)
+    }
+    /// `size` is a fixed-point value with 6 decimal places
     pub fn open_position(
         ctx: Context<OpenPosition>,
         pair: String,
-        size: f64,
+        size: u64,
         leverage: u16,
         position_type: u8,
         direction: u8,
@@ -50,10 +209,108 @@ pub mod bond {
     pub fn close_position(ctx: Context<ClosePosition>, identity: u8) -> Result<()> {
         position::close_position(ctx, identity)
     }
+    /// Close part of a position, the rest stays open
+    pub fn reduce_position(ctx: Context<ClosePosition>, size: u64) -> Result<()> {
+        position::reduce_position(ctx, size)
+    }
+    /// Move funds from the balance into the margin of an independent position
+    pub fn add_margin(ctx: Context<UpdateMargin>, amount: u64) -> Result<()> {
+        margin::add_margin(ctx, amount)
+    }
+    /// Move funds from the margin of an independent position back to the balance
+    pub fn remove_margin(ctx: Context<UpdateMargin>, amount: u64) -> Result<()> {
+        margin::remove_margin(ctx, amount)
+    }
+    /// Place a limit order filled by a keeper once the price reaches `open_price`
+    #[allow(clippy::too_many_arguments)]
+    pub fn place_limit_order(
+        ctx: Context<PlaceLimitOrder>,
+        pair: String,
+        size: u64,
+        leverage: u16,
+        position_type: u8,
+        direction: u8,
+        open_price: u64,
+        validity_time: i64,
+    ) -> Result<()> {
+        order::place_limit_order(
+            ctx,
+            pair,
+            size,
+            leverage,
+            position_type,
+            direction,
+            open_price,
+            validity_time,
+        )
+    }
+    /// Cancel a pending order, by its owner or by anyone once expired
+    pub fn cancel_order(ctx: Context<CancelOrder>) -> Result<()> {
+        order::cancel_order(ctx)
+    }
+    /// Fill a pending order whose price was reached
+    pub fn fill_order(ctx: Context<FillOrder>) -> Result<()> {
+        order::fill_order(ctx)
+    }
+    /// Set the stop surplus and stop loss prices of a position
+    pub fn set_position_triggers(
+        ctx: Context<SetPositionTriggers>,
+        stop_surplus_price: u64,
+        stop_loss_price: u64,
+    ) -> Result<()> {
+        trigger::set_position_triggers(ctx, stop_surplus_price, stop_loss_price)
+    }
+    /// Close a position whose trigger price was reached, callable by anyone
+    pub fn execute_trigger(ctx: Context<ExecuteTrigger>) -> Result<()> {
+        trigger::execute_trigger(ctx)
+    }
+    /// Liquidate a position below the liquidation line, callable by anyone
+    pub fn liquidate_position(ctx: Context<LiquidatePosition>) -> Result<()> {
+        liquidation::liquidate_position(ctx)
+    }
+    /// Liquidate the full positions of an account below the liquidation line, callable by anyone
+    pub fn liquidate_account(ctx: Context<LiquidateAccount>, market_count: u8) -> Result<()> {
+        liquidation::liquidate_account(ctx, market_count)
+    }
+    /// Create the LP share mint of a market, by the market authority
+    pub fn initialize_lp_mint(ctx: Context<InitializeLpMint>, pair: String) -> Result<()> {
+        market::initialize_lp_mint(ctx, pair)
+    }
+    /// Provide liquidity, LP shares are minted at the pool net asset value
     pub fn investment(ctx: Context<Investment>, pair: String, amount: u64) -> Result<()> {
         market::investment(ctx, pair, amount)
     }
-    pub fn divestment(ctx: Context<Divestment>, pair: String, amount: u64) -> Result<()> {
-        market::divestment(ctx, pair, amount)
+    /// Request to withdraw LP shares after the divestment cooldown of the market
+    pub fn request_divestment(
+        ctx: Context<RequestDivestment>,
+        pair: String,
+        shares: u64,
+    ) -> Result<()> {
+        market::request_divestment(ctx, pair, shares)
+    }
+    /// Burn the requested LP shares for their part of the pool net asset value
+    pub fn execute_divestment(ctx: Context<ExecuteDivestment>, pair: String) -> Result<()> {
+        market::execute_divestment(ctx, pair)
+    }
+    /// Realised and unrealised yield of a liquidity provider
+    pub fn get_investor_yield(
+        ctx: Context<GetInvestorYield>,
+        pair: String,
+    ) -> Result<InvestorYield> {
+        market::get_investor_yield(ctx, pair)
+    }
+    /// Provide liquidity through an NFT bond locked for `lock_term` seconds
+    pub fn bond_investment(
+        ctx: Context<BondInvestment>,
+        pair: String,
+        amount: u64,
+        lock_term: i64,
+        uri: String,
+    ) -> Result<()> {
+        nft_bond::bond_investment(ctx, pair, amount, lock_term, uri)
+    }
+    /// Redeem an NFT bond after its lock term, by the holder of the NFT
+    pub fn bond_divestment(ctx: Context<BondDivestment>, pair: String) -> Result<()> {
+        nft_bond::bond_divestment(ctx, pair)
     }
 }
@@ -0,0 +1,102 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program::invoke_signed,
+};
+
+/// Metaplex token metadata program, the owner of every NFT metadata account.
+pub fn get_program_id() -> Pubkey {
+    Pubkey::try_from("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s").unwrap()
+}
+
+pub const METADATA_SEED: &[u8] = b"metadata";
+/// Longest uri accepted by the metadata program
+pub const MAX_URI_LENGTH: usize = 200;
+
+// Index of `CreateMetadataAccountV3` in the metadata program instruction enum
+const CREATE_METADATA_ACCOUNT_V3: u8 = 33;
+
+#[derive(AnchorSerialize)]
+struct Creator {
+    address: Pubkey,
+    verified: bool,
+    share: u8,
+}
+
+// Leading fields of `DataV2`, followed in the instruction data by the collection and uses
+// options, `is_mutable` and the collection details option.
+#[derive(AnchorSerialize)]
+struct MetadataData {
+    name: String,
+    symbol: String,
+    uri: String,
+    seller_fee_basis_points: u16,
+    creators: Option<Vec<Creator>>,
+}
+
+/// Accounts of `CreateMetadataAccountV3`
+pub struct CreateMetadataAccount<'info> {
+    pub metadata: AccountInfo<'info>,
+    pub mint: AccountInfo<'info>,
+    pub mint_authority: AccountInfo<'info>,
+    pub payer: AccountInfo<'info>,
+    pub update_authority: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+    pub rent: AccountInfo<'info>,
+}
+
+/// Create the immutable metadata of an NFT minted by a program authority.
+/// The update authority is the only creator and is verified, so wallets can tell
+/// the NFTs of the program from copies.
+pub fn create_metadata_account<'info>(
+    program: AccountInfo<'info>,
+    accounts: CreateMetadataAccount<'info>,
+    name: String,
+    symbol: String,
+    uri: String,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    let mut data = vec![CREATE_METADATA_ACCOUNT_V3];
+    MetadataData {
+        name,
+        symbol,
+        uri,
+        seller_fee_basis_points: 0,
+        creators: Some(vec![Creator {
+            address: accounts.update_authority.key(),
+            verified: true,
+            share: 100,
+        }]),
+    }
+    .serialize(&mut data)?;
+    // no collection, no uses, immutable, no collection details
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    let ix = Instruction {
+        program_id: get_program_id(),
+        accounts: vec![
+            AccountMeta::new(accounts.metadata.key(), false),
+            AccountMeta::new_readonly(accounts.mint.key(), false),
+            AccountMeta::new_readonly(accounts.mint_authority.key(), true),
+            AccountMeta::new(accounts.payer.key(), true),
+            AccountMeta::new_readonly(accounts.update_authority.key(), true),
+            AccountMeta::new_readonly(accounts.system_program.key(), false),
+            AccountMeta::new_readonly(accounts.rent.key(), false),
+        ],
+        data,
+    };
+    invoke_signed(
+        &ix,
+        &[
+            accounts.metadata,
+            accounts.mint,
+            accounts.mint_authority,
+            accounts.payer,
+            accounts.update_authority,
+            accounts.system_program,
+            accounts.rent,
+            program,
+        ],
+        signer_seeds,
+    )?;
+    Ok(())
+}
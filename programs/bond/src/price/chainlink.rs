@@ -0,0 +1,82 @@
+use crate::decimal::Decimal;
+use crate::errors::BondError;
+use crate::price::price::Quote;
+use anchor_lang::prelude::*;
+use std::convert::TryInto;
+
+/// Chainlink OCR2 store program, the owner of every Solana feed account.
+pub fn get_store_program() -> Pubkey {
+    Pubkey::try_from("HEvSKofvBgfaexv23kMabbYqxasxU3mQ4ibBMEmJWHny").unwrap()
+}
+
+// Layout of the store `Transmissions` account:
+// 8 bytes discriminator, 192 bytes header, then a ring buffer of live transmissions.
+const DISCRIMINATOR_SIZE: usize = 8;
+const HEADER_SIZE: usize = 192;
+const DECIMALS_OFFSET: usize = DISCRIMINATOR_SIZE + 1 + 1 + 32 * 3 + 32;
+const LATEST_ROUND_ID_OFFSET: usize = DECIMALS_OFFSET + 1 + 4;
+const LIVE_LENGTH_OFFSET: usize = LATEST_ROUND_ID_OFFSET + 4 + 1;
+const LIVE_CURSOR_OFFSET: usize = LIVE_LENGTH_OFFSET + 4;
+const TRANSMISSION_SIZE: usize = 8 + 4 + 4 + 16 + 8 + 8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Round {
+    pub answer: i128,
+    pub timestamp: u32,
+    pub decimals: u8,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data
+        .get(offset..offset + 4)
+        .ok_or(BondError::GetPriceFailedFromChainLink)?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Read the latest round of a feed without a CPI into the store program.
+pub fn latest_round_data(price_account_info: &AccountInfo) -> Result<Round> {
+    if *price_account_info.owner != get_store_program() {
+        return Err(BondError::InvalidPriceAccount.into());
+    }
+    let data = price_account_info.try_borrow_data()?;
+    let decimals = *data
+        .get(DECIMALS_OFFSET)
+        .ok_or(BondError::GetPriceFailedFromChainLink)?;
+    let latest_round_id = read_u32(&data, LATEST_ROUND_ID_OFFSET)?;
+    let live_length = read_u32(&data, LIVE_LENGTH_OFFSET)?;
+    let live_cursor = read_u32(&data, LIVE_CURSOR_OFFSET)?;
+    if latest_round_id == 0 || live_length == 0 {
+        return Err(BondError::GetPriceFailedFromChainLink.into());
+    }
+    // the cursor points at the next slot to be written
+    let index = (live_cursor + live_length - 1) % live_length;
+    let offset = DISCRIMINATOR_SIZE + HEADER_SIZE + index as usize * TRANSMISSION_SIZE;
+    let timestamp = read_u32(&data, offset + 8)?;
+    let answer = data
+        .get(offset + 16..offset + 32)
+        .ok_or(BondError::GetPriceFailedFromChainLink)?;
+    Ok(Round {
+        answer: i128::from_le_bytes(answer.try_into().unwrap()),
+        timestamp,
+        decimals,
+    })
+}
+
+pub fn get_price_from_chainlink(price_account_info: &AccountInfo) -> Result<Quote> {
+    let round = latest_round_data(price_account_info).map_err(|err| {
+        msg!("chainlink latest_round_data error:{:?}", err);
+        BondError::GetPriceFailedFromChainLink
+    })?;
+    let answer: i64 = round
+        .answer
+        .try_into()
+        .map_err(|_| BondError::GetPriceFailedFromChainLink)?;
+    if answer <= 0 {
+        return Err(BondError::GetPriceFailedFromChainLink.into());
+    }
+    Ok(Quote {
+        price: Decimal::from_price(answer, -(round.decimals as i32))?,
+        conf: Decimal::ZERO,
+        publish_time: round.timestamp as i64,
+    })
+}
@@ -1 +1,2 @@
+pub mod chainlink;
 pub mod price;
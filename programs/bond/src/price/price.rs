@@ -1,19 +1,96 @@
-use crate::com;
+use crate::decimal::Decimal;
 use crate::errors::BondError;
+use crate::price::chainlink::get_price_from_chainlink;
 use anchor_lang::prelude::*;
+#[cfg(not(feature = "mock-oracle"))]
 use pyth_sdk_solana::{load_price_feed_from_account_info, Price, PriceFeed};
+use std::convert::TryFrom;
 
-pub fn get_price(
+/// A single oracle reading.
+#[derive(Debug, Clone, Copy)]
+pub struct Quote {
+    pub price: Decimal,
+    /// Confidence interval, 0 when the source does not publish one
+    pub conf: Decimal,
+    /// Unix timestamp of the reading
+    pub publish_time: i64,
+}
+
+/// Pyth is the primary source and chainlink the fallback when pyth fails.
+/// With a non-zero `max_divergence` both feeds are read and their median is used provided
+/// they do not disagree by more than that ratio, a single source is never trusted alone.
+/// Every quote must pass `check` before it is used, otherwise the source counts as failed.
+/// A failure returns the error of the failing source, the pyth one when both fail.
+pub fn get_price<F>(
     price_account_info: &AccountInfo,
-    _price_account_info_chinalink: &AccountInfo,
-) -> Result<f64> {
-    get_price_from_pyth(price_account_info)
-    // todo ,if error then get price from chainlink
+    price_account_info_chinalink: &AccountInfo,
+    max_divergence: Decimal,
+    check: F,
+) -> Result<Decimal>
+where
+    F: Fn(&Quote) -> Result<()>,
+{
+    let pyth_price = get_price_from_pyth(price_account_info).and_then(|q| {
+        check(&q)?;
+        Ok(q.price)
+    });
+    let get_chainlink_price = || {
+        get_price_from_chainlink(price_account_info_chinalink).and_then(|q| {
+            check(&q)?;
+            Ok(q.price)
+        })
+    };
+    if max_divergence.is_zero() {
+        return pyth_price.or_else(|pyth_err| {
+            msg!(
+                "pyth price unavailable, fallback to chainlink:{:?}",
+                pyth_err
+            );
+            get_chainlink_price().map_err(move |chainlink_err| {
+                msg!(
+                    "all price sources failed, pyth:{:?},chainlink:{:?}",
+                    pyth_err,
+                    chainlink_err
+                );
+                pyth_err
+            })
+        });
+    }
+    match (pyth_price, get_chainlink_price()) {
+        (Ok(p), Ok(c)) => aggregate(p, c, max_divergence),
+        (Ok(_), Err(err)) => {
+            msg!("chainlink price unavailable:{:?}", err);
+            Err(err)
+        }
+        (Err(err), Ok(_)) => {
+            msg!("pyth price unavailable:{:?}", err);
+            Err(err)
+        }
+        (Err(pyth_err), Err(chainlink_err)) => {
+            msg!(
+                "all price sources failed, pyth:{:?},chainlink:{:?}",
+                pyth_err,
+                chainlink_err
+            );
+            Err(pyth_err)
+        }
+    }
+}
+
+// median of the two quotes, rejected when their divergence exceeds the threshold
+fn aggregate(a: Decimal, b: Decimal, max_divergence: Decimal) -> Result<Decimal> {
+    let median = a.checked_add(b)?.checked_div_int(2)?;
+    let divergence = a.checked_sub(b)?.checked_abs()?.checked_div(median)?;
+    if divergence > max_divergence {
+        msg!("price divergence:{},pyth:{},chainlink:{}", divergence, a, b);
+        return Err(BondError::PriceDivergenceTooLarge.into());
+    }
+    Ok(median)
 }
 
 // get price from pyth.network
-#[cfg(any(feature = "devnet", feature = "testnet", feature = "mainnetbeta"))]
-fn get_price_from_pyth(price_account_info: &AccountInfo) -> Result<f64> {
+#[cfg(not(feature = "mock-oracle"))]
+fn get_price_from_pyth(price_account_info: &AccountInfo) -> Result<Quote> {
     let price_feed: PriceFeed =
         load_price_feed_from_account_info(&price_account_info).map_err(|err| {
             msg!("load_price_feed_from_account_info error:{:?}", err);
@@ -22,14 +99,129 @@ fn get_price_from_pyth(price_account_info: &AccountInfo) -> Result<f64> {
     let current_price: Price = price_feed
         .get_current_price()
         .ok_or(BondError::GetPriceFailedFromPyth)?;
-    let price = com::f64_round(
-        (current_price.price as f64 / 10u64.pow(current_price.expo.abs() as u32) as f64)
-            * com::DECIMALS,
-    );
-    Ok(price)
+    Ok(Quote {
+        price: Decimal::from_price(current_price.price, current_price.expo)?,
+        conf: Decimal::from_price(
+            i64::try_from(current_price.conf).map_err(|_| BondError::GetPriceFailedFromPyth)?,
+            current_price.expo,
+        )?,
+        publish_time: price_feed.publish_time,
+    })
 }
 
-#[cfg(any(feature = "localhost"))]
-fn get_price_from_pyth(_price_account_info: &AccountInfo) -> Result<f64> {
-    Ok(125.26 * com::DECIMALS)
+// get price from a feed of the mock oracle program in test builds
+#[cfg(feature = "mock-oracle")]
+fn get_price_from_pyth(price_account_info: &AccountInfo) -> Result<Quote> {
+    use mock_oracle::MockPrice;
+    if *price_account_info.owner != mock_oracle::ID {
+        return Err(BondError::InvalidPriceAccount.into());
+    }
+    let data = price_account_info.try_borrow_data()?;
+    let mock_price = MockPrice::try_deserialize(&mut &data[..]).map_err(|err| {
+        msg!("load mock price error:{:?}", err);
+        BondError::GetPriceFailedFromPyth
+    })?;
+    Ok(Quote {
+        price: Decimal::from_price(mock_price.price, mock_price.expo)?,
+        conf: Decimal::from_price(
+            i64::try_from(mock_price.conf).map_err(|_| BondError::GetPriceFailedFromPyth)?,
+            mock_price.expo,
+        )?,
+        publish_time: mock_price.publish_time,
+    })
+}
+
+#[cfg(all(test, not(feature = "mock-oracle")))]
+mod tests {
+    use super::*;
+    use crate::state::market::Market;
+    use pyth_sdk_solana::state::{AccountType, PriceAccount, PriceStatus, MAGIC, VERSION_2};
+
+    fn pyth_price_account(publish_time: i64) -> PriceAccount {
+        let mut account = PriceAccount {
+            magic: MAGIC,
+            ver: VERSION_2,
+            atype: AccountType::Price as u32,
+            expo: -6,
+            timestamp: publish_time,
+            ..Default::default()
+        };
+        account.agg.price = 20_000_000_000;
+        account.agg.status = PriceStatus::Trading;
+        account
+    }
+
+    #[test]
+    fn test_get_price_returns_the_stale_price_error() {
+        let market = Market {
+            max_price_age: 60,
+            ..Default::default()
+        };
+        let now = 1000;
+        let key = Pubkey::default();
+        let (mut pyth_lamports, mut chainlink_lamports) = (0, 0);
+        let mut chainlink_data = [];
+        // no chainlink feed to fall back to
+        let chainlink = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut chainlink_lamports,
+            &mut chainlink_data,
+            &key,
+            false,
+            0,
+        );
+        for (publish_time, expected) in [
+            (now - 61, Err(BondError::StalePrice.into())),
+            (now - 60, Ok(Decimal::from_integer(20000).unwrap())),
+        ] {
+            let mut pyth_account = pyth_price_account(publish_time);
+            let pyth = AccountInfo::new(
+                &key,
+                false,
+                false,
+                &mut pyth_lamports,
+                bytemuck::bytes_of_mut(&mut pyth_account),
+                &key,
+                false,
+                0,
+            );
+            let price = get_price(&pyth, &chainlink, Decimal::ZERO, |q| {
+                market.check_quote(q, now)
+            });
+            assert_eq!(price, expected);
+        }
+        // with aggregation the error of the failing source is returned
+        let mut pyth_account = pyth_price_account(now - 61);
+        let pyth = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut pyth_lamports,
+            bytemuck::bytes_of_mut(&mut pyth_account),
+            &key,
+            false,
+            0,
+        );
+        let price = get_price(&pyth, &chainlink, Decimal::new(1, 2), |q| {
+            market.check_quote(q, now)
+        });
+        assert_eq!(price, Err(BondError::StalePrice.into()));
+        let mut pyth_account = pyth_price_account(now);
+        let pyth = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut pyth_lamports,
+            bytemuck::bytes_of_mut(&mut pyth_account),
+            &key,
+            false,
+            0,
+        );
+        let price = get_price(&pyth, &chainlink, Decimal::new(1, 2), |q| {
+            market.check_quote(q, now)
+        });
+        assert_eq!(price, Err(BondError::GetPriceFailedFromChainLink.into()));
+    }
 }
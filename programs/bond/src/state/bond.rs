@@ -0,0 +1,48 @@
+use crate::com;
+use crate::decimal::Decimal;
+use anchor_lang::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Terms of an NFT bond, the bond belongs to whoever holds its NFT.
+#[account]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Bond {
+    /// Mint of the bond NFT
+    pub mint: Pubkey,
+    /// Market account the liquidity is provided to
+    pub market_account: Pubkey,
+    /// Tokens invested by the bond
+    pub principal: Decimal,
+    /// LP shares held in escrow for the bond
+    pub shares: Decimal,
+    /// Unix timestamp of the issuance
+    pub issue_time: i64,
+    /// Lock term in seconds, the bond can only be redeemed after it
+    pub lock_term: i64,
+    /// Extra share of the profit paid on redemption
+    pub boost: Decimal,
+}
+
+impl Bond {
+    pub const LEN: usize = 32 * 2 + 8 * 5;
+
+    /// Profit share boost of a bond locked for `lock_term` with the `boost_rate` of the market
+    pub fn get_boost(boost_rate: Decimal, lock_term: i64) -> Result<Decimal> {
+        boost_rate
+            .checked_mul_int(lock_term)?
+            .checked_div_int(com::BOND_BOOST_PERIOD)
+    }
+
+    pub fn is_locked(&self, now: i64) -> bool {
+        now < self.issue_time + self.lock_term
+    }
+
+    /// Extra tokens paid on top of a `payout`, the boost only applies to a profit
+    pub fn get_boost_amount(&self, payout: Decimal) -> Result<Decimal> {
+        let profit = payout.checked_sub(self.principal)?;
+        if !profit.is_positive() {
+            return Ok(Decimal::ZERO);
+        }
+        profit.checked_mul(self.boost)
+    }
+}
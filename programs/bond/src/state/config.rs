@@ -0,0 +1,61 @@
+use crate::errors::BondError;
+use anchor_lang::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Protocol wide keys, a singleton initialized once by the program upgrade authority
+/// and updatable by its governance authority.
+#[account]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Config {
+    /// Governance authority, the only account allowed to update the config
+    pub authority: Pubkey,
+    /// Mint address of the vault token, the test version is the circulating token issued by the project,
+    /// and the official network is USDC. Set once at initialization, the vault depends on it.
+    pub vault_mint: Pubkey,
+    /// The team authorized account is used to initialize and set the official trading market
+    pub team_authority: Pubkey,
+    /// Address of project development fund wallet
+    pub project_fund_wallet: Pubkey,
+    /// Clearing robot, allowed to force close positions
+    pub clearing_robot: Pubkey,
+    /// Registry of the markets supporting full position mode,
+    /// the slot of a market is its `full_position_id` - 1
+    pub full_position_markets: [Pubkey; MAX_FULL_POSITION_MARKETS],
+}
+
+/// Number of markets the full position registry can hold
+pub const MAX_FULL_POSITION_MARKETS: usize = 32;
+
+/// The keys of `Config` set by governance.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct ConfigParams {
+    pub team_authority: Pubkey,
+    pub project_fund_wallet: Pubkey,
+    pub clearing_robot: Pubkey,
+}
+
+impl Config {
+    pub const LEN: usize = 32 * 5 + 32 * MAX_FULL_POSITION_MARKETS;
+    pub fn set_params(&mut self, params: &ConfigParams) {
+        self.team_authority = params.team_authority;
+        self.project_fund_wallet = params.project_fund_wallet;
+        self.clearing_robot = params.clearing_robot;
+    }
+    /// Register a market for full positions and return its id,
+    /// a market registered before keeps its id
+    pub fn register_full_position_market(&mut self, market: Pubkey, id: u16) -> Result<u16> {
+        if id > 0 {
+            if self.full_position_markets.get(id as usize - 1) != Some(&market) {
+                return Err(BondError::InvalidFullPositionMarket.into());
+            }
+            return Ok(id);
+        }
+        let slot = self
+            .full_position_markets
+            .iter()
+            .position(|k| *k == Pubkey::default())
+            .ok_or(BondError::FullPositionRegistryFull)?;
+        self.full_position_markets[slot] = market;
+        Ok(slot as u16 + 1)
+    }
+}
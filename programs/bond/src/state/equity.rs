@@ -0,0 +1,222 @@
+use crate::decimal::Decimal;
+use crate::errors::BondError;
+use crate::state::{market, position};
+use anchor_lang::prelude::*;
+
+/// Value of a position if it were closed now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionValuation {
+    pub position_seed_offset: u32,
+    /// P/L between the open price and the close quote
+    pub price_pl: Decimal,
+    /// Funding received since the position was opened, negative when paid
+    pub funding: Decimal,
+    /// Closing transaction fee and accrued management fee
+    pub fees: Decimal,
+}
+
+impl PositionValuation {
+    /// P/L realised by closing the position now
+    pub fn get_pl(&self) -> Result<Decimal> {
+        self.price_pl
+            .checked_add(self.funding)?
+            .checked_sub(self.fees)
+    }
+}
+
+/// Value of the full position mode of an account, with the detail of every position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Equity {
+    pub balance: Decimal,
+    pub positions: Vec<PositionValuation>,
+    /// Floating P/L of all the positions
+    pub total_pl: Decimal,
+}
+
+impl Equity {
+    pub fn get_equity(&self) -> Result<Decimal> {
+        self.balance.checked_add(self.total_pl)
+    }
+    /// The equity ratio to `margin` is at or above `burst_rate`, always true without margin
+    pub fn is_above(&self, margin: Decimal, burst_rate: Decimal) -> Result<bool> {
+        if !margin.is_positive() {
+            return Ok(true);
+        }
+        Ok(self.get_equity()?.checked_div(margin)? >= burst_rate)
+    }
+}
+
+/// Value a position with the market it was opened on and the current price of that market.
+pub fn value_position(
+    header: &position::PositionHeader,
+    market: &market::Market,
+    price: &market::Price,
+    now: i64,
+) -> Result<PositionValuation> {
+    let fund_size = header.get_fund_size()?;
+    let fees = market
+        .get_transaction_fee(fund_size)?
+        .checked_add(market.get_management_fee(fund_size, header.open_time, now)?)?;
+    Ok(PositionValuation {
+        position_seed_offset: header.position_seed_offset,
+        price_pl: header.get_pl_price(price)?,
+        funding: market.get_position_fund(header.direction, fund_size, header.funding_index)?,
+        fees,
+    })
+}
+
+/// Value the full positions of an account.
+/// `markets` pairs each market with its own current price, every position is valued
+/// with the pair whose `full_position_id` matches its `market_id`.
+pub fn evaluate_full_positions(
+    balance: Decimal,
+    headers: &[position::PositionHeader],
+    markets: &[(&market::Market, &market::Price)],
+    now: i64,
+) -> Result<Equity> {
+    let mut positions = Vec::with_capacity(headers.len());
+    let mut total_pl = Decimal::ZERO;
+    for header in headers.iter() {
+        let (market, price) = markets
+            .iter()
+            .find(|(m, _)| m.full_position_id == header.market_id)
+            .ok_or(BondError::InvalidFullPositionMarket)?;
+        let valuation = value_position(header, market, price, now)?;
+        total_pl = total_pl.checked_add(valuation.get_pl()?)?;
+        positions.push(valuation);
+    }
+    Ok(Equity {
+        balance,
+        positions,
+        total_pl,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(id: u16) -> market::Market {
+        market::Market {
+            full_position_id: id,
+            transaction_rate: Decimal::new(1, 3),
+            management_rate: Decimal::new(1, 3),
+            ..Default::default()
+        }
+    }
+
+    fn price(real_price: i64, spread: i64) -> market::Price {
+        let real_price = Decimal::from_integer(real_price).unwrap();
+        let spread = Decimal::from_integer(spread).unwrap();
+        market::Price {
+            buy_price: real_price.checked_add(spread).unwrap(),
+            sell_price: real_price.checked_sub(spread).unwrap(),
+            real_price,
+            spread,
+        }
+    }
+
+    fn header(
+        offset: u32,
+        market_id: u16,
+        direction: position::Direction,
+        open_price: i64,
+    ) -> position::PositionHeader {
+        position::PositionHeader {
+            position_seed_offset: offset,
+            open_price: Decimal::from_integer(open_price).unwrap(),
+            direction,
+            size: Decimal::ONE,
+            margin: Decimal::from_integer(100).unwrap(),
+            market_id,
+            funding_index: Decimal::ZERO,
+            open_time: 0,
+        }
+    }
+
+    #[test]
+    fn test_value_position_breakdown() {
+        let mut m = market(1);
+        m.long_funding_index = Decimal::new(-1, 2);
+        let h = header(1, 1, position::Direction::Buy, 1000);
+        let v = value_position(&h, &m, &price(1100, 10), 0).unwrap();
+        // closed at the sell quote 1090
+        assert_eq!(v.price_pl, Decimal::from_integer(90).unwrap());
+        assert_eq!(v.funding, Decimal::from_integer(-10).unwrap());
+        // closing fee only, no holding time
+        assert_eq!(v.fees, Decimal::ONE);
+        assert_eq!(v.get_pl().unwrap(), Decimal::from_integer(79).unwrap());
+        // plus one management fee period
+        let now = crate::com::MANAGEMENT_FEE_PERIOD;
+        let v = value_position(&h, &m, &price(1100, 10), now).unwrap();
+        assert_eq!(v.fees, Decimal::from_integer(2).unwrap());
+    }
+
+    #[test]
+    fn test_evaluate_uses_the_price_of_each_market() {
+        let (btc, eth) = (market(1), market(2));
+        let (btc_price, eth_price) = (price(20000, 100), price(1000, 1));
+        let headers = vec![
+            header(1, 1, position::Direction::Buy, 20000),
+            header(2, 2, position::Direction::Sell, 1100),
+        ];
+        let equity = evaluate_full_positions(
+            Decimal::from_integer(1000).unwrap(),
+            &headers,
+            &[(&btc, &btc_price), (&eth, &eth_price)],
+            0,
+        )
+        .unwrap();
+        assert_eq!(
+            equity.positions[0].price_pl,
+            Decimal::from_integer(-100).unwrap()
+        );
+        // the short closes at the eth buy quote, not with the btc spread
+        assert_eq!(
+            equity.positions[1].price_pl,
+            Decimal::from_integer(99).unwrap()
+        );
+        let fees = Decimal::new(211, 1);
+        assert_eq!(
+            equity.total_pl,
+            Decimal::from_integer(-1)
+                .unwrap()
+                .checked_sub(fees)
+                .unwrap()
+        );
+        assert_eq!(
+            equity.get_equity().unwrap(),
+            Decimal::from_integer(999)
+                .unwrap()
+                .checked_sub(fees)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_evaluate_requires_every_market() {
+        let btc = market(1);
+        let btc_price = price(20000, 100);
+        let headers = vec![header(1, 2, position::Direction::Buy, 1000)];
+        assert!(
+            evaluate_full_positions(Decimal::ZERO, &headers, &[(&btc, &btc_price)], 0).is_err()
+        );
+        assert!(evaluate_full_positions(Decimal::ZERO, &[], &[], 0)
+            .unwrap()
+            .positions
+            .is_empty());
+    }
+
+    #[test]
+    fn test_is_above() {
+        let equity = Equity {
+            balance: Decimal::from_integer(100).unwrap(),
+            positions: vec![],
+            total_pl: Decimal::from_integer(-50).unwrap(),
+        };
+        let margin = Decimal::from_integer(100).unwrap();
+        assert!(equity.is_above(margin, Decimal::new(5, 1)).unwrap());
+        assert!(!equity.is_above(margin, Decimal::new(6, 1)).unwrap());
+        assert!(equity.is_above(Decimal::ZERO, Decimal::new(6, 1)).unwrap());
+    }
+}
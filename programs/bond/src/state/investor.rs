@@ -0,0 +1,228 @@
+use crate::com;
+use crate::decimal::Decimal;
+use crate::errors::BondError;
+use anchor_lang::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Liquidity provided by a wallet to a market.
+#[account]
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InvestorAccount {
+    /// Wallet of the liquidity provider
+    pub authority: Pubkey,
+    /// Market account the liquidity is provided to
+    pub market_account: Pubkey,
+    /// Tokens invested and not divested yet
+    pub principal: Decimal,
+    /// LP shares minted for this record and not burned yet
+    pub shares: Decimal,
+    /// Average net asset value per share paid by the investments
+    pub entry_nav: Decimal,
+    /// Profit realised by the divestments, negative for a loss
+    pub realised_profit: Decimal,
+    /// Unix timestamp of the last investment
+    pub deposit_time: i64,
+    /// Shares requested for divestment, zero without a pending request
+    pub pending_shares: Decimal,
+    /// Unix timestamp of the pending divestment request
+    pub request_time: i64,
+    /// Cooldown (seconds) of the market when the pending request was made
+    pub request_cooldown: i64,
+}
+
+/// Yield of a liquidity provider, returned by `get_investor_yield`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Deserialize, Serialize)]
+pub struct InvestorYield {
+    pub principal: Decimal,
+    /// Current value of the shares at the pool net asset value
+    pub value: Decimal,
+    pub realised_profit: Decimal,
+    pub unrealised_profit: Decimal,
+}
+
+impl InvestorAccount {
+    pub const LEN: usize = 32 * 2 + 8 * 4 + 8 + 8 * 3;
+
+    /// Record an investment of `amount` tokens for `shares` new shares
+    pub fn invest(&mut self, amount: Decimal, shares: Decimal, now: i64) -> Result<()> {
+        let total_shares = self.shares.checked_add(shares)?;
+        self.entry_nav = self
+            .entry_nav
+            .checked_mul(self.shares)?
+            .checked_add(amount)?
+            .checked_div(total_shares)?;
+        self.principal = self.principal.checked_add(amount)?;
+        self.shares = total_shares;
+        self.deposit_time = now;
+        Ok(())
+    }
+
+    /// Record a divestment of `shares` paid `payout` tokens,
+    /// the principal is reduced pro rata to the recorded shares and the difference is realised.
+    /// LP tokens are transferable so the shares burned may exceed the recorded ones,
+    /// the extra shares come without principal.
+    pub fn divest(&mut self, shares: Decimal, payout: Decimal) -> Result<()> {
+        let recorded = shares.min(self.shares);
+        let principal = if self.shares.is_zero() {
+            Decimal::ZERO
+        } else {
+            self.principal.checked_mul_div(recorded, self.shares)?
+        };
+        self.realised_profit = self
+            .realised_profit
+            .checked_add(payout.checked_sub(principal)?)?;
+        self.principal = self.principal.checked_sub(principal)?;
+        self.shares = self.shares.checked_sub(recorded)?;
+        Ok(())
+    }
+
+    /// Record a request to divest `shares` after `cooldown` seconds, replacing any pending request.
+    /// The shares held are checked against the LP token account, not this record.
+    pub fn request_divestment(&mut self, shares: Decimal, cooldown: i64, now: i64) -> Result<()> {
+        if !shares.is_positive() {
+            return Err(BondError::InvalidParameterOfPosition.into());
+        }
+        self.pending_shares = shares;
+        self.request_time = now;
+        self.request_cooldown = cooldown;
+        Ok(())
+    }
+
+    /// Unix timestamp from which the pending request can be executed
+    pub fn get_divestment_executable_time(&self) -> i64 {
+        self.request_time + self.request_cooldown
+    }
+
+    /// Take the pending request once its cooldown has passed,
+    /// within `com::DIVESTMENT_EXECUTION_WINDOW` after that
+    pub fn take_pending_divestment(&mut self, now: i64) -> Result<Decimal> {
+        if !self.pending_shares.is_positive() {
+            return Err(BondError::DivestmentNotRequested.into());
+        }
+        let executable_time = self.get_divestment_executable_time();
+        if now < executable_time {
+            return Err(BondError::DivestmentCooldown.into());
+        }
+        if now > executable_time + com::DIVESTMENT_EXECUTION_WINDOW {
+            return Err(BondError::DivestmentRequestExpired.into());
+        }
+        let shares = self.pending_shares;
+        self.pending_shares = Decimal::ZERO;
+        Ok(shares)
+    }
+
+    /// Yield of the record with shares worth `nav` per `supply` shares
+    pub fn get_yield(&self, nav: Decimal, supply: Decimal) -> Result<InvestorYield> {
+        let value = if supply.is_zero() {
+            Decimal::ZERO
+        } else {
+            nav.checked_mul_div(self.shares, supply)?
+        };
+        Ok(InvestorYield {
+            principal: self.principal,
+            value,
+            realised_profit: self.realised_profit,
+            unrealised_profit: value.checked_sub(self.principal)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(n: i64) -> Decimal {
+        Decimal::from_integer(n).unwrap()
+    }
+
+    fn investor(principal: i64, shares: i64) -> InvestorAccount {
+        InvestorAccount {
+            authority: Pubkey::default(),
+            market_account: Pubkey::default(),
+            principal: d(principal),
+            shares: d(shares),
+            entry_nav: Decimal::ONE,
+            realised_profit: Decimal::ZERO,
+            deposit_time: 0,
+            pending_shares: Decimal::ZERO,
+            request_time: 0,
+            request_cooldown: 0,
+        }
+    }
+
+    #[test]
+    fn test_divest_reduces_the_principal_pro_rata() {
+        let mut i = investor(100, 100);
+        i.divest(d(40), d(50)).unwrap();
+        assert_eq!(i.principal, d(60));
+        assert_eq!(i.shares, d(60));
+        assert_eq!(i.realised_profit, d(10));
+    }
+
+    #[test]
+    fn test_divest_shares_received_by_transfer() {
+        // shares held beyond the record carry no principal
+        let mut i = investor(100, 100);
+        i.divest(d(150), d(150)).unwrap();
+        assert_eq!(i.principal, Decimal::ZERO);
+        assert_eq!(i.shares, Decimal::ZERO);
+        assert_eq!(i.realised_profit, d(50));
+
+        let mut i = investor(0, 0);
+        i.divest(d(10), d(12)).unwrap();
+        assert_eq!(i.realised_profit, d(12));
+    }
+
+    #[test]
+    fn test_request_divestment_beyond_the_record() {
+        let mut i = investor(0, 0);
+        i.request_divestment(d(10), 0, 5).unwrap();
+        assert_eq!(i.pending_shares, d(10));
+        assert!(i.request_divestment(Decimal::ZERO, 0, 5).is_err());
+    }
+
+    #[test]
+    fn test_take_pending_divestment_after_the_cooldown() {
+        let mut i = investor(100, 100);
+        assert_eq!(
+            i.take_pending_divestment(0),
+            Err(BondError::DivestmentNotRequested.into())
+        );
+        i.request_divestment(d(10), 3600, 1000).unwrap();
+        assert_eq!(
+            i.take_pending_divestment(4599),
+            Err(BondError::DivestmentCooldown.into())
+        );
+        assert_eq!(i.take_pending_divestment(4600).unwrap(), d(10));
+        // the request is taken once
+        assert_eq!(
+            i.take_pending_divestment(4600),
+            Err(BondError::DivestmentNotRequested.into())
+        );
+    }
+
+    #[test]
+    fn test_take_pending_divestment_expires_after_the_window() {
+        let mut i = investor(100, 100);
+        i.request_divestment(d(10), 3600, 1000).unwrap();
+        let last = 4600 + com::DIVESTMENT_EXECUTION_WINDOW;
+        assert_eq!(
+            i.take_pending_divestment(last + 1),
+            Err(BondError::DivestmentRequestExpired.into())
+        );
+        assert_eq!(i.take_pending_divestment(last).unwrap(), d(10));
+    }
+
+    #[test]
+    fn test_take_pending_divestment_keeps_the_requested_cooldown() {
+        // the cooldown of the market when the request was made applies
+        let mut i = investor(100, 100);
+        i.request_divestment(d(10), 3600, 1000).unwrap();
+        assert_eq!(
+            i.take_pending_divestment(1000),
+            Err(BondError::DivestmentCooldown.into())
+        );
+        i.request_divestment(d(10), 0, 1000).unwrap();
+        assert_eq!(i.take_pending_divestment(1000).unwrap(), d(10));
+    }
+}
@@ -1,41 +1,47 @@
 use crate::com;
+use crate::decimal::Decimal;
 use crate::errors::BondError;
 use crate::price::price;
+use crate::state::bond;
 use crate::state::position;
 use anchor_lang::prelude::*;
 use num_enum::TryFromPrimitive;
 use serde::{Deserialize, Serialize};
 
 #[account]
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Market {
     /// Maximum allowable leverage ratio
     pub max_leverage: u16,
     /// position management rate, space for future derived values
-    pub management_rate: f64,
+    pub management_rate: Decimal,
     /// transaction rate , space for future derived values
-    pub transaction_rate: f64,
+    pub transaction_rate: Decimal,
     /// insurance rate
-    pub insurance_rate: f64,
+    pub insurance_rate: Decimal,
     /// margin rate,Current constant positioning 100%
-    pub margin_rate: f64,
+    pub margin_rate: Decimal,
     /// Market status:
     /// 1 Normal;
     /// 2. Lock the market, allow closing settlement and not open positions;
     /// 3 The market is frozen, and opening and closing positions are not allowed.
     pub status: MarketStatus,
-    /// Total amount of outstanding NFT bonds.
+    /// Principal of the liquidity provided, including the outstanding NFT bonds.
     pub vault_full: u64,
     /// Token balance of basic current fund.
-    pub vault_base_balance: f64,
+    pub vault_base_balance: Decimal,
     /// Token balance of profit and loss fund
-    pub vault_profit_balance: f64,
+    pub vault_profit_balance: Decimal,
     /// Insurance fund token balance
-    pub vault_insurance_balance: f64,
+    pub vault_insurance_balance: Decimal,
     /// Total amount of long positions in the market
-    pub long_position_total: f64,
+    pub long_position_total: Decimal,
     /// Total amount of short positions in the market
-    pub short_position_total: f64,
+    pub short_position_total: Decimal,
+    /// Total size of the long positions, used to value them at the current price
+    pub long_size_total: Decimal,
+    /// Total size of the short positions, used to value them at the current price
+    pub short_size_total: Decimal,
     /// Market administrator account address
     pub authority: Pubkey,
     /// Market operator address, with authority to operate rate, up to 5 can be set.
@@ -47,49 +53,335 @@ pub struct Market {
     pub pair: String,
     /// Point difference (can be understood as slip point),
     /// deviation between the executed quotation and the actual quotation
-    pub spread: f64,
+    pub spread: Decimal,
     /// Market operator, 1 project party, other marks to be defined
     pub officer: bool,
-    /// Whether full position  mode is supported
+    /// Whether full position  mode is supported, set by governance through the registry
     pub is_support_full_position: bool,
+    /// Maximum relative divergence allowed between pyth and chainlink quotes.
+    /// 0 disables aggregation, pyth is used with chainlink as fallback;
+    /// otherwise both quotes are required.
+    pub max_price_divergence: Decimal,
+    /// Maximum age of an oracle price in seconds, 0 disables the check.
+    pub max_price_age: i64,
+    /// Maximum ratio of the confidence interval to the price, 0 disables the check.
+    pub max_confidence_ratio: Decimal,
+    /// Liquidation penalty, as a proportion of the position fund size
+    pub liquidation_penalty_rate: Decimal,
+    /// Share of the liquidation penalty paid to the liquidator, the rest goes to the insurance fund
+    pub liquidation_reward_rate: Decimal,
+    /// Cumulative funding received per unit of fund size by long positions, negative when paid
+    pub long_funding_index: Decimal,
+    /// Cumulative funding received per unit of fund size by short positions, negative when paid
+    pub short_funding_index: Decimal,
+    /// Unix timestamp of the last funding accrual
+    pub funding_update_time: i64,
+    /// Share of the transaction and management fees paid to the project fund,
+    /// the rest goes to the profit and loss pool of the liquidity providers
+    pub fee_project_share: Decimal,
+    /// Fees owed to the project fund wallet and not yet collected from the vault
+    pub vault_project_fee_balance: Decimal,
+    /// Leverage limits by position fund size, sorted by `min_fund_size`,
+    /// unused tiers have a zero `max_leverage`
+    pub leverage_tiers: [LeverageTier; MAX_LEVERAGE_TIERS],
+    /// Risk-control thresholds of the market
+    pub risk_params: RiskParams,
+    /// Id of the market in the full position registry of the config, 0 when never registered.
+    /// It is kept when the market is removed so the open positions can still be evaluated.
+    pub full_position_id: u16,
+    /// Principal of the outstanding NFT bonds, part of `vault_full`.
+    pub bond_principal: u64,
+    /// Extra share of the profit paid to a bond per `com::BOND_BOOST_PERIOD` of lock term
+    pub bond_boost_rate: Decimal,
+    /// Seconds between a divestment request and its execution
+    pub divestment_cooldown: i64,
+    /// Cumulative bad debt settled by the market
+    pub bad_debt_total: Decimal,
+    /// Cumulative bad debt borne by the liquidity providers
+    pub socialised_loss_total: Decimal,
 }
+
+/// Per-market risk-control thresholds, the defaults are the `com` constants.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct RiskParams {
+    /// Maximum exposure as a proportion of the liquidity pool
+    pub position_diff_proportion: Decimal,
+    /// Maximum unidirectional positions as a proportion of the liquidity pool
+    pub position_proportion: Decimal,
+    /// Maximum size of a single position as a proportion of the liquidity pool
+    pub position_proportion_one: Decimal,
+    /// Funding rate per funding period at a 100% exposure proportion
+    pub fund_rate: Decimal,
+    /// Liquidation line of independent positions
+    pub burst_rate: Decimal,
+}
+
+impl RiskParams {
+    pub const LEN: usize = 8 * 5;
+    pub fn check(&self) -> Result<()> {
+        if !self.position_diff_proportion.is_positive()
+            || !self.position_proportion.is_positive()
+            || !self.position_proportion_one.is_positive()
+            || self.fund_rate.is_negative()
+            || !self.burst_rate.is_positive()
+            || self.burst_rate > Decimal::ONE
+        {
+            return Err(BondError::InvalidMarketParameter.into());
+        }
+        Ok(())
+    }
+}
+
+impl Default for RiskParams {
+    fn default() -> Self {
+        RiskParams {
+            position_diff_proportion: com::POSITION_DIFF_PROPORTION,
+            position_proportion: com::POSITION_PROPORTION,
+            position_proportion_one: com::POSITION_PROPORTION_ONE,
+            fund_rate: com::FUND_RATE,
+            burst_rate: com::BURST_RATE,
+        }
+    }
+}
+
+/// Number of leverage tiers a market can define
+pub const MAX_LEVERAGE_TIERS: usize = 5;
+
+/// Positions whose fund size reaches `min_fund_size` are limited to `max_leverage`.
+#[derive(
+    AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, Deserialize, Serialize,
+)]
+pub struct LeverageTier {
+    pub min_fund_size: Decimal,
+    pub max_leverage: u16,
+}
+
+impl LeverageTier {
+    pub const LEN: usize = 8 + 2;
+}
+/// Outcome of `Market::settle_pl`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlSettlement {
+    /// P/L actually settled with the trader, less the unpaid part of a profit
+    pub pl: Decimal,
+    pub bad_debt: Decimal,
+    /// Part of the bad debt covered by the insurance fund
+    pub insurance_covered: Decimal,
+    /// Part of the bad debt borne by the liquidity providers
+    pub socialised: Decimal,
+    /// Part of a profit beyond the insurance fund and all the liquidity
+    pub unpaid: Decimal,
+}
+
+/// Emitted when a settlement leaves bad debt
+#[event]
+pub struct BadDebtEvent {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub position_seed_offset: u32,
+    pub bad_debt: Decimal,
+    pub insurance_covered: Decimal,
+    pub socialised: Decimal,
+    pub unpaid: Decimal,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Price {
-    pub buy_price: f64,
-    pub sell_price: f64,
-    pub real_price: f64,
-    pub spread: f64,
+    pub buy_price: Decimal,
+    pub sell_price: Decimal,
+    pub real_price: Decimal,
+    pub spread: Decimal,
 }
 
 impl Market {
-    pub const LEN: usize =
-        2 + 8 * 4 + (1 + 1) + 8 * 6 + 32 + (32 * 5) + 32 * 2 + (4 + 20) + 8 + 1 + 1;
+    pub const LEN: usize = 2
+        + 8 * 4
+        + (1 + 1)
+        + 8 * 8
+        + 32
+        + (32 * 5)
+        + 32 * 2
+        + (4 + 20)
+        + 8
+        + 1
+        + 1
+        + 8 * 10
+        + LeverageTier::LEN * MAX_LEVERAGE_TIERS
+        + RiskParams::LEN
+        + 2
+        + 8 * 5;
     // get current price
     pub fn get_price(
         &self,
         price_account_info_pyth: &AccountInfo,
         price_account_info_chinalink: &AccountInfo,
     ) -> Result<Price> {
-        let p = price::get_price(price_account_info_pyth, price_account_info_chinalink)?;
+        let now = Clock::get()?.unix_timestamp;
+        let p = price::get_price(
+            price_account_info_pyth,
+            price_account_info_chinalink,
+            self.max_price_divergence,
+            |quote| self.check_quote(quote, now),
+        )?;
 
-        // let spread = com::f64_round(p * self.spread);
-        let mut sell_price = p - self.spread;
-        let buy_price = p + self.spread;
+        let mut sell_price = p.checked_sub(self.spread)?;
+        let buy_price = p.checked_add(self.spread)?;
         if p < self.spread {
-            sell_price = 0.0;
+            sell_price = Decimal::ZERO;
         }
-        if sell_price < 0.0 || buy_price < sell_price {
+        if sell_price.is_negative() || buy_price < sell_price {
             return Err(BondError::PriceError.into());
         }
         Ok(Price {
-            buy_price: com::f64_round(buy_price),
-            sell_price: com::f64_round(sell_price),
+            buy_price,
+            sell_price,
             real_price: p,
             spread: self.spread,
         })
     }
-    pub fn get_exposure(&self) -> f64 {
-        (self.long_position_total.abs() - self.short_position_total.abs()).abs()
+    // reject stale and low confidence quotes
+    pub fn check_quote(&self, quote: &price::Quote, now: i64) -> Result<()> {
+        if self.max_price_age > 0 && now - quote.publish_time > self.max_price_age {
+            msg!(
+                "stale price, publish_time:{},now:{}",
+                quote.publish_time,
+                now
+            );
+            return Err(BondError::StalePrice.into());
+        }
+        if !quote.price.is_positive() {
+            return Err(BondError::PriceError.into());
+        }
+        if self.max_confidence_ratio.is_positive()
+            && quote.conf.checked_div(quote.price)? > self.max_confidence_ratio
+        {
+            msg!("low confidence price:{},conf:{}", quote.price, quote.conf);
+            return Err(BondError::PriceConfidenceTooLow.into());
+        }
+        Ok(())
+    }
+    /// Settle the P/L realised by a trader against the pools.
+    /// Losses refill the basic pool up to `vault_full` and the rest goes to the profit and loss pool.
+    /// `uncovered_loss` is the part of a loss the trader could not pay beyond their margin.
+    ///
+    /// A profit the profit and loss pool cannot pay, or an uncovered loss, is bad debt.
+    /// The insurance fund covers it first and the remainder is socialised across the LP shares:
+    /// the rest of a profit is paid from the basic pool and written down against `vault_full`,
+    /// the rest of an uncovered loss is never received by the pools.
+    /// Only a profit beyond the insurance fund and all the liquidity is left unpaid.
+    pub fn settle_pl(&mut self, pl: Decimal, uncovered_loss: Decimal) -> Result<PlSettlement> {
+        let insurance = self.vault_insurance_balance.max(Decimal::ZERO);
+        let settlement = if !pl.is_negative() {
+            let from_profit = pl.min(self.vault_profit_balance.max(Decimal::ZERO));
+            self.vault_profit_balance = self.vault_profit_balance.checked_sub(from_profit)?;
+            let bad_debt = pl.checked_sub(from_profit)?;
+            let insurance_covered = bad_debt.min(insurance);
+            let remainder = bad_debt.checked_sub(insurance_covered)?;
+            let socialised = remainder.min(self.vault_base_balance.max(Decimal::ZERO));
+            self.vault_base_balance = self.vault_base_balance.checked_sub(socialised)?;
+            self.vault_full = self
+                .vault_full
+                .saturating_sub(socialised.to_token_amount()?);
+            let unpaid = remainder.checked_sub(socialised)?;
+            PlSettlement {
+                pl: pl.checked_sub(unpaid)?,
+                bad_debt,
+                insurance_covered,
+                socialised,
+                unpaid,
+            }
+        } else {
+            let full_level = Decimal::from_token_amount(self.vault_full)?;
+            let insurance_covered = uncovered_loss.min(insurance);
+            self.vault_base_balance = self
+                .vault_base_balance
+                .checked_add(pl.checked_abs()?)?
+                .checked_add(insurance_covered)?;
+            let d = self.vault_base_balance.checked_sub(full_level)?;
+            if d.is_positive() {
+                self.vault_profit_balance = self.vault_profit_balance.checked_add(d)?;
+                self.vault_base_balance = full_level;
+            }
+            PlSettlement {
+                pl,
+                bad_debt: uncovered_loss,
+                insurance_covered,
+                socialised: uncovered_loss.checked_sub(insurance_covered)?,
+                unpaid: Decimal::ZERO,
+            }
+        };
+        if settlement.bad_debt.is_positive() {
+            self.vault_insurance_balance = self
+                .vault_insurance_balance
+                .checked_sub(settlement.insurance_covered)?;
+            self.bad_debt_total = self.bad_debt_total.checked_add(settlement.bad_debt)?;
+            self.socialised_loss_total = self
+                .socialised_loss_total
+                .checked_add(settlement.socialised)?;
+        }
+        Ok(settlement)
+    }
+    /// Maximum leverage allowed for a position of this fund size
+    pub fn get_max_leverage(&self, fund_size: Decimal) -> u16 {
+        self.leverage_tiers
+            .iter()
+            .filter(|t| t.max_leverage > 0 && fund_size >= t.min_fund_size)
+            .map(|t| t.max_leverage)
+            .fold(self.max_leverage, u16::min)
+    }
+    /// Tiers must have increasing fund sizes and decreasing leverage within the market cap
+    pub fn check_leverage_tiers(&self, tiers: &[LeverageTier]) -> Result<()> {
+        if tiers.len() > MAX_LEVERAGE_TIERS {
+            return Err(BondError::InvalidMarketParameter.into());
+        }
+        for (i, tier) in tiers.iter().enumerate() {
+            if tier.max_leverage == 0 || tier.max_leverage > self.max_leverage {
+                return Err(BondError::InvalidMarketParameter.into());
+            }
+            if i > 0
+                && (tier.min_fund_size <= tiers[i - 1].min_fund_size
+                    || tier.max_leverage > tiers[i - 1].max_leverage)
+            {
+                return Err(BondError::InvalidMarketParameter.into());
+            }
+        }
+        Ok(())
+    }
+    /// The market authority or one of its operators
+    pub fn is_operator(&self, key: &Pubkey) -> bool {
+        self.authority == *key
+            || (*key != Pubkey::default() && self.operator.iter().any(|o| o == key))
+    }
+    /// Split a fee between the project fund and the profit and loss pool
+    pub fn distribute_fee(&mut self, fee: Decimal) -> Result<()> {
+        let project_fee = fee.checked_mul(self.fee_project_share)?;
+        self.vault_project_fee_balance = self.vault_project_fee_balance.checked_add(project_fee)?;
+        self.vault_profit_balance = self
+            .vault_profit_balance
+            .checked_add(fee.checked_sub(project_fee)?)?;
+        Ok(())
+    }
+    /// Transaction fee charged to open or close a position of this fund size
+    pub fn get_transaction_fee(&self, fund_size: Decimal) -> Result<Decimal> {
+        fund_size.checked_mul(self.transaction_rate)
+    }
+    /// Management fee accrued by a position of this fund size held since `open_time`
+    pub fn get_management_fee(
+        &self,
+        fund_size: Decimal,
+        open_time: i64,
+        now: i64,
+    ) -> Result<Decimal> {
+        fund_size
+            .checked_mul(self.management_rate)?
+            .checked_mul_int((now - open_time).max(0))?
+            .checked_div_int(com::MANAGEMENT_FEE_PERIOD)
+    }
+    pub fn get_exposure(&self) -> Result<Decimal> {
+        self.long_position_total
+            .checked_abs()?
+            .checked_sub(self.short_position_total.checked_abs()?)?
+            .checked_abs()
     }
 
     pub fn get_dominant_direction(&self) -> position::Direction {
@@ -100,20 +392,172 @@ impl Market {
         }
     }
 
-    pub fn get_total_liquidity(&self) -> f64 {
-        self.vault_base_balance + self.vault_profit_balance
+    /// Floating P/L of all the open positions at `price`, positive when the traders win
+    pub fn get_unrealised_pl(&self, price: &Price) -> Result<Decimal> {
+        let long_pl = price
+            .sell_price
+            .checked_mul(self.long_size_total)?
+            .checked_sub(self.long_position_total)?;
+        let short_pl = self
+            .short_position_total
+            .checked_sub(price.buy_price.checked_mul(self.short_size_total)?)?;
+        long_pl.checked_add(short_pl)
+    }
+    /// Net asset value of the liquidity pool: its balances minus what the traders are owed
+    pub fn get_nav(&self, price: &Price) -> Result<Decimal> {
+        self.get_total_liquidity()?
+            .checked_sub(self.get_unrealised_pl(price)?)
+    }
+    /// Add `amount` invested tokens to the basic pool, returns the shares they are worth
+    /// at the net asset value `nav` of `supply` shares.
+    /// A pool without shares starts at one token per share, the value it still holds belongs to
+    /// nobody and goes to the insurance fund rather than to the first liquidity provider.
+    /// A deficit is never passed on to the new liquidity: the insurance fund brings the pool back
+    /// to zero without shares, or to `com::MIN_SHARE_PRICE` per share, and the deposit is rejected
+    /// when the fund cannot.
+    pub fn add_liquidity(&mut self, amount: u64, nav: Decimal, supply: Decimal) -> Result<Decimal> {
+        let amount_decimal = Decimal::from_token_amount(amount)?;
+        let shares = if supply.is_zero() {
+            let value = nav.min(self.get_total_liquidity()?);
+            if value.is_positive() {
+                self.sweep_orphaned_liquidity(value)?;
+            } else if value.is_negative() {
+                self.cover_pool_deficit(value.checked_neg()?)?;
+            }
+            amount_decimal
+        } else {
+            let min_nav = supply.checked_mul(com::MIN_SHARE_PRICE)?;
+            if nav < min_nav {
+                self.cover_pool_deficit(min_nav.checked_sub(nav)?)?;
+            }
+            amount_decimal.checked_mul_div(supply, nav.max(min_nav))?
+        };
+        if !shares.is_positive() {
+            return Err(BondError::InvalidParameterOfPosition.into());
+        }
+        self.vault_full += amount;
+        self.vault_base_balance = self.vault_base_balance.checked_add(amount_decimal)?;
+        Ok(shares)
+    }
+    // move the realised value of a pool without shares to the insurance fund
+    fn sweep_orphaned_liquidity(&mut self, value: Decimal) -> Result<()> {
+        let from_profit = value.min(self.vault_profit_balance.max(Decimal::ZERO));
+        self.vault_profit_balance = self.vault_profit_balance.checked_sub(from_profit)?;
+        self.vault_base_balance = self
+            .vault_base_balance
+            .checked_sub(value.checked_sub(from_profit)?)?;
+        self.vault_insurance_balance = self.vault_insurance_balance.checked_add(value)?;
+        self.vault_full = 0;
+        msg!("orphaned liquidity moved to the insurance fund:{}", value);
+        Ok(())
+    }
+    // move a deficit of the pool from the insurance fund into the basic pool
+    fn cover_pool_deficit(&mut self, deficit: Decimal) -> Result<()> {
+        if deficit > self.vault_insurance_balance {
+            msg!(
+                "pool deficit:{} exceeds the insurance fund:{}",
+                deficit,
+                self.vault_insurance_balance
+            );
+            return Err(BondError::PoolNavNotPositive.into());
+        }
+        self.vault_insurance_balance = self.vault_insurance_balance.checked_sub(deficit)?;
+        self.vault_base_balance = self.vault_base_balance.checked_add(deficit)?;
+        msg!("pool deficit covered by the insurance fund:{}", deficit);
+        Ok(())
+    }
+    /// Take `shares` of `supply` out of the pools at the net asset value `nav`, returns the payout.
+    /// The basic pool gives its share, the profit and loss pool covers the rest of the payout.
+    pub fn remove_liquidity(
+        &mut self,
+        shares: Decimal,
+        supply: Decimal,
+        nav: Decimal,
+    ) -> Result<Decimal> {
+        if !shares.is_positive() || shares > supply {
+            return Err(BondError::InvalidParameterOfPosition.into());
+        }
+        if !nav.is_positive() {
+            return Err(BondError::PoolNavNotPositive.into());
+        }
+        let payout = nav.checked_mul_div(shares, supply)?;
+        let base = self.vault_base_balance.checked_mul_div(shares, supply)?;
+        let principal =
+            Decimal::from_token_amount(self.vault_full)?.checked_mul_div(shares, supply)?;
+        self.vault_full -= principal.to_token_amount()?;
+        self.vault_base_balance = self.vault_base_balance.checked_sub(base)?;
+        self.vault_profit_balance = self
+            .vault_profit_balance
+            .checked_sub(payout.checked_sub(base)?)?;
+        Ok(payout)
+    }
+    /// Take the escrowed shares of a redeemed `bond` out of the pools, returns the payout
+    /// and the boost paid on top of it from the profit and loss pool.
+    /// The exposure limit is checked once the boost has left the pool.
+    pub fn redeem_bond(
+        &mut self,
+        bond: &bond::Bond,
+        supply: Decimal,
+        nav: Decimal,
+    ) -> Result<(Decimal, Decimal)> {
+        let payout = self.remove_liquidity(bond.shares, supply, nav)?;
+        // the boost cannot take more than the profit and loss pool holds
+        let boost = bond
+            .get_boost_amount(payout)?
+            .min(self.vault_profit_balance.max(Decimal::ZERO));
+        self.vault_profit_balance = self.vault_profit_balance.checked_sub(boost)?;
+        self.bond_principal = self
+            .bond_principal
+            .saturating_sub(bond.principal.to_token_amount()?);
+        self.check_exposure_limit()?;
+        Ok((payout, boost))
+    }
+    pub fn get_total_liquidity(&self) -> Result<Decimal> {
+        self.vault_base_balance
+            .checked_add(self.vault_profit_balance)
+    }
+
+    pub fn get_exposure_proportion(&self) -> Result<Decimal> {
+        let exposure = self.get_exposure()?;
+        if exposure.is_zero() {
+            return Ok(Decimal::ZERO);
+        }
+        exposure.checked_div(self.get_total_liquidity()?)
     }
 
-    pub fn get_exposure_proportion(&self) -> f64 {
-        let exposure = self.get_exposure();
-        if exposure == 0.0 {
-            return 0.0;
+    /// Liquidity can only leave the pool while the exposure stays within
+    /// `risk_params.position_diff_proportion` of what remains
+    pub fn check_exposure_limit(&self) -> Result<()> {
+        if self.get_exposure()?.is_zero() {
+            return Ok(());
         }
-        exposure / self.get_total_liquidity()
+        if !self.get_total_liquidity()?.is_positive()
+            || self.get_exposure_proportion()? > self.risk_params.position_diff_proportion
+        {
+            return Err(BondError::DivestmentExceedsExposureLimit.into());
+        }
+        Ok(())
     }
 
-    pub fn get_fund_rate(&self) -> f64 {
-        self.get_exposure_proportion() * com::FUND_RATE
+    /// Funding rate per funding period, the exposure proportion is capped at
+    /// `com::MAX_FUNDING_EXPOSURE_PROPORTION` so an empty or overdrawn pool charges the maximum
+    /// rate instead of blocking every settlement of the market
+    pub fn get_fund_rate(&self) -> Result<Decimal> {
+        let exposure = self.get_exposure()?;
+        if exposure.is_zero() {
+            return Ok(Decimal::ZERO);
+        }
+        let liquidity = self.get_total_liquidity()?;
+        let proportion = if liquidity.is_positive() {
+            exposure
+                .checked_div(liquidity)
+                .map_or(com::MAX_FUNDING_EXPOSURE_PROPORTION, |p| {
+                    p.min(com::MAX_FUNDING_EXPOSURE_PROPORTION)
+                })
+        } else {
+            com::MAX_FUNDING_EXPOSURE_PROPORTION
+        };
+        proportion.checked_mul(self.risk_params.fund_rate)
     }
 
     pub fn get_current_dominant_direction(&self) -> position::Direction {
@@ -123,20 +567,62 @@ impl Market {
         position::Direction::Sell
     }
 
+    /// Accrue the funding since the last update into the cumulative indexes.
+    /// The dominant side pays the fund rate on its fund size, pro rata to the elapsed
+    /// funding periods, and the other side receives the same rate if it holds positions.
+    pub fn update_funding(&mut self, now: i64) -> Result<()> {
+        let elapsed = now - self.funding_update_time;
+        if elapsed <= 0 {
+            return Ok(());
+        }
+        let rate = self
+            .get_fund_rate()?
+            .checked_mul_int(elapsed)?
+            .checked_div_int(com::FUNDING_PERIOD)?;
+        // keep accumulating time while the accrual is below the precision
+        if rate.is_zero() && !self.get_exposure()?.is_zero() {
+            return Ok(());
+        }
+        let receive = if self
+            .long_position_total
+            .min(self.short_position_total)
+            .is_zero()
+        {
+            Decimal::ZERO
+        } else {
+            rate
+        };
+        match self.get_current_dominant_direction() {
+            position::Direction::Buy => {
+                self.long_funding_index = self.long_funding_index.checked_sub(rate)?;
+                self.short_funding_index = self.short_funding_index.checked_add(receive)?;
+            }
+            position::Direction::Sell => {
+                self.short_funding_index = self.short_funding_index.checked_sub(rate)?;
+                self.long_funding_index = self.long_funding_index.checked_add(receive)?;
+            }
+        }
+        self.funding_update_time = now;
+        Ok(())
+    }
+
+    pub fn get_funding_index(&self, direction: position::Direction) -> Decimal {
+        match direction {
+            position::Direction::Buy => self.long_funding_index,
+            position::Direction::Sell => self.short_funding_index,
+        }
+    }
+
+    /// Funding received by a position since it snapshotted `funding_index`, negative when paid
     pub fn get_position_fund(
         &self,
         direction: position::Direction,
-        position_fund_size: f64,
-    ) -> f64 {
-        if direction == self.get_current_dominant_direction() {
-            return -(position_fund_size * self.get_fund_rate());
-        }
-        let max = self.long_position_total.max(self.short_position_total);
-        let min = self.long_position_total.min(self.short_position_total);
-        let total_deducted_funds = max * self.get_fund_rate();
-        // Total funds allocated to disadvantaged positions
-        let total_funds_allocated = total_deducted_funds * (min / max);
-        total_funds_allocated * (position_fund_size / min)
+        position_fund_size: Decimal,
+        funding_index: Decimal,
+    ) -> Result<Decimal> {
+        self.get_funding_index(direction)
+            .checked_sub(funding_index)?
+            .checked_mul(position_fund_size)
     }
 }
 #[derive(
@@ -144,6 +630,7 @@ impl Market {
     AnchorDeserialize,
     Clone,
     Debug,
+    Default,
     TryFromPrimitive,
     PartialEq,
     Deserialize,
@@ -151,7 +638,258 @@ impl Market {
 )]
 #[repr(u8)]
 pub enum MarketStatus {
+    #[default]
     Normal = 1,
     Locked,
     Frozen,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(base: i64, profit: i64, insurance: i64) -> Market {
+        Market {
+            vault_full: base as u64 * 1_000_000,
+            vault_base_balance: Decimal::from_integer(base).unwrap(),
+            vault_profit_balance: Decimal::from_integer(profit).unwrap(),
+            vault_insurance_balance: Decimal::from_integer(insurance).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    fn d(n: i64) -> Decimal {
+        Decimal::from_integer(n).unwrap()
+    }
+
+    #[test]
+    fn test_fund_rate_is_capped_when_the_pool_is_empty() {
+        let mut m = market(100, 0, 0);
+        m.long_position_total = d(50);
+        // 50% exposure of the pool
+        assert_eq!(m.get_fund_rate().unwrap(), Decimal::new(5, 3));
+        m.long_position_total = d(500);
+        assert_eq!(m.get_fund_rate().unwrap(), com::FUND_RATE);
+        for base in [0, -10] {
+            m.vault_base_balance = d(base);
+            assert_eq!(m.get_fund_rate().unwrap(), com::FUND_RATE);
+            m.funding_update_time = 0;
+            m.update_funding(com::FUNDING_PERIOD).unwrap();
+            assert_eq!(m.funding_update_time, com::FUNDING_PERIOD);
+        }
+        assert_eq!(
+            m.long_funding_index,
+            com::FUND_RATE.checked_mul_int(-2).unwrap()
+        );
+        // a tiny pool does not overflow the proportion
+        m.vault_base_balance = Decimal::from_raw(1);
+        m.long_position_total = Decimal::from_raw(i64::MAX / 2);
+        assert_eq!(m.get_fund_rate().unwrap(), com::FUND_RATE);
+    }
+
+    #[test]
+    fn test_check_exposure_limit() {
+        let mut m = market(100, 0, 0);
+        assert!(m.check_exposure_limit().is_ok());
+        // balanced positions leave no exposure, even with an empty pool
+        m.long_position_total = d(80);
+        m.short_position_total = d(80);
+        m.vault_base_balance = Decimal::ZERO;
+        assert!(m.check_exposure_limit().is_ok());
+        // 70% of the pool is the limit
+        m.short_position_total = d(10);
+        m.vault_base_balance = d(100);
+        assert!(m.check_exposure_limit().is_ok());
+        m.short_position_total = d(9);
+        assert_eq!(
+            m.check_exposure_limit(),
+            Err(BondError::DivestmentExceedsExposureLimit.into())
+        );
+        // an empty or overdrawn pool cannot carry any exposure
+        m.short_position_total = d(79);
+        for base in [0, -10] {
+            m.vault_base_balance = d(base);
+            assert_eq!(
+                m.check_exposure_limit(),
+                Err(BondError::DivestmentExceedsExposureLimit.into())
+            );
+        }
+    }
+
+    #[test]
+    fn test_redeem_bond_checks_the_exposure_after_the_boost() {
+        let bond = |boost| bond::Bond {
+            mint: Pubkey::default(),
+            market_account: Pubkey::default(),
+            principal: d(50),
+            shares: d(100),
+            issue_time: 0,
+            lock_term: 0,
+            boost,
+        };
+        let mut m = market(100, 100, 0);
+        m.long_position_total = d(65);
+        // 65% of the remaining pool without the boost
+        let (payout, boost) = m.redeem_bond(&bond(Decimal::ZERO), d(200), d(200)).unwrap();
+        assert_eq!(payout, d(100));
+        assert!(boost.is_zero());
+
+        let mut m = market(100, 100, 0);
+        m.long_position_total = d(65);
+        // the boost takes the remaining pool to 50, 130% exposure
+        assert_eq!(
+            m.redeem_bond(&bond(Decimal::ONE), d(200), d(200)),
+            Err(BondError::DivestmentExceedsExposureLimit.into())
+        );
+    }
+
+    #[test]
+    fn test_add_liquidity_prices_shares_at_the_nav() {
+        let mut m = market(100, 0, 0);
+        let shares = m.add_liquidity(50_000_000, d(200), d(100)).unwrap();
+        assert_eq!(shares, d(25));
+        assert_eq!(m.vault_full, 150_000_000);
+        assert_eq!(m.vault_base_balance, d(150));
+    }
+
+    #[test]
+    fn test_add_liquidity_sweeps_a_pool_without_shares() {
+        let mut m = market(100, 20, 0);
+        // the value left without shares is not given to the first liquidity provider
+        let shares = m.add_liquidity(10_000_000, d(120), Decimal::ZERO).unwrap();
+        assert_eq!(shares, d(10));
+        assert_eq!(m.vault_insurance_balance, d(120));
+        assert_eq!(m.vault_profit_balance, Decimal::ZERO);
+        assert_eq!(m.vault_base_balance, d(10));
+        assert_eq!(m.vault_full, 10_000_000);
+    }
+
+    #[test]
+    fn test_add_liquidity_covers_a_deficit_from_the_insurance() {
+        let mut m = market(0, 0, 100);
+        // 51 brings the pool to the share price floor, the deposit keeps its value
+        let shares = m.add_liquidity(100_000_000, d(-50), d(100)).unwrap();
+        assert_eq!(shares, d(10000));
+        assert_eq!(m.vault_insurance_balance, d(49));
+        assert_eq!(m.vault_base_balance, d(151));
+        // an overdrawn pool without shares is brought back to zero first
+        let mut m = market(0, 0, 30);
+        m.vault_base_balance = d(-10);
+        let shares = m.add_liquidity(10_000_000, d(-10), Decimal::ZERO).unwrap();
+        assert_eq!(shares, d(10));
+        assert_eq!(m.vault_insurance_balance, d(20));
+        assert_eq!(m.vault_base_balance, d(10));
+    }
+
+    #[test]
+    fn test_add_liquidity_rejects_a_deficit_beyond_the_insurance() {
+        let mut m = market(0, 0, 50);
+        assert_eq!(
+            m.add_liquidity(100_000_000, d(-50), d(100)),
+            Err(BondError::PoolNavNotPositive.into())
+        );
+        let mut m = market(0, 0, 5);
+        m.vault_base_balance = d(-10);
+        assert_eq!(
+            m.add_liquidity(10_000_000, d(-10), Decimal::ZERO),
+            Err(BondError::PoolNavNotPositive.into())
+        );
+        // nothing moved
+        assert_eq!(m.vault_insurance_balance, d(5));
+        assert_eq!(m.vault_base_balance, d(-10));
+    }
+
+    #[test]
+    fn test_settle_pl_pays_a_profit_from_the_profit_pool() {
+        let mut m = market(100, 20, 30);
+        let s = m.settle_pl(d(15), Decimal::ZERO).unwrap();
+        assert_eq!(s.pl, d(15));
+        assert!(s.bad_debt.is_zero());
+        assert_eq!(m.vault_profit_balance, d(5));
+        assert_eq!(m.vault_base_balance, d(100));
+        assert_eq!(m.vault_insurance_balance, d(30));
+        assert!(m.bad_debt_total.is_zero());
+    }
+
+    #[test]
+    fn test_settle_pl_covers_a_profit_with_the_insurance() {
+        let mut m = market(100, 20, 30);
+        let s = m.settle_pl(d(45), Decimal::ZERO).unwrap();
+        assert_eq!(s.pl, d(45));
+        assert_eq!(s.bad_debt, d(25));
+        assert_eq!(s.insurance_covered, d(25));
+        assert!(s.socialised.is_zero());
+        assert_eq!(m.vault_profit_balance, Decimal::ZERO);
+        assert_eq!(m.vault_insurance_balance, d(5));
+        // the liquidity providers are untouched
+        assert_eq!(m.vault_base_balance, d(100));
+        assert_eq!(m.vault_full, 100_000_000);
+    }
+
+    #[test]
+    fn test_settle_pl_socialises_a_profit_beyond_the_insurance() {
+        let mut m = market(100, 20, 30);
+        let s = m.settle_pl(d(80), Decimal::ZERO).unwrap();
+        // the trader is paid in full
+        assert_eq!(s.pl, d(80));
+        assert_eq!(s.bad_debt, d(60));
+        assert_eq!(s.insurance_covered, d(30));
+        assert_eq!(s.socialised, d(30));
+        assert!(s.unpaid.is_zero());
+        assert_eq!(m.vault_insurance_balance, Decimal::ZERO);
+        assert_eq!(m.vault_base_balance, d(70));
+        assert_eq!(m.vault_full, 70_000_000);
+        assert_eq!(m.bad_debt_total, d(60));
+        assert_eq!(m.socialised_loss_total, d(30));
+    }
+
+    #[test]
+    fn test_settle_pl_refills_the_base_pool_up_to_vault_full() {
+        let mut m = market(100, 0, 0);
+        m.vault_base_balance = d(70);
+        m.settle_pl(d(-20), Decimal::ZERO).unwrap();
+        assert_eq!(m.vault_base_balance, d(90));
+        assert_eq!(m.vault_profit_balance, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_settle_pl_overflows_a_loss_into_the_profit_pool() {
+        let mut m = market(100, 5, 0);
+        m.vault_base_balance = d(90);
+        m.settle_pl(d(-25), Decimal::ZERO).unwrap();
+        assert_eq!(m.vault_base_balance, d(100));
+        assert_eq!(m.vault_profit_balance, d(20));
+    }
+
+    #[test]
+    fn test_settle_pl_leaves_a_profit_beyond_all_the_liquidity_unpaid() {
+        let mut m = market(100, 20, 30);
+        let s = m.settle_pl(d(200), Decimal::ZERO).unwrap();
+        assert_eq!(s.pl, d(150));
+        assert_eq!(s.bad_debt, d(180));
+        assert_eq!(s.insurance_covered, d(30));
+        assert_eq!(s.socialised, d(100));
+        assert_eq!(s.unpaid, d(50));
+        assert_eq!(m.vault_base_balance, Decimal::ZERO);
+        assert_eq!(m.vault_profit_balance, Decimal::ZERO);
+        assert_eq!(m.vault_insurance_balance, Decimal::ZERO);
+        assert_eq!(m.vault_full, 0);
+    }
+
+    #[test]
+    fn test_settle_pl_covers_an_uncovered_loss_with_the_insurance() {
+        let mut m = market(100, 0, 30);
+        let s = m.settle_pl(d(-10), d(40)).unwrap();
+        assert_eq!(s.pl, d(-10));
+        assert_eq!(s.bad_debt, d(40));
+        assert_eq!(s.insurance_covered, d(30));
+        // the remainder is never received by the pools
+        assert_eq!(s.socialised, d(10));
+        assert!(s.unpaid.is_zero());
+        assert_eq!(m.socialised_loss_total, d(10));
+        // the margin and the insurance reach the pools, above the basic pool level
+        assert_eq!(m.vault_base_balance, d(100));
+        assert_eq!(m.vault_profit_balance, d(40));
+        assert_eq!(m.vault_insurance_balance, Decimal::ZERO);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod bond;
+pub mod config;
+pub mod equity;
+pub mod investor;
 pub mod market;
 pub mod position;
 pub mod user;
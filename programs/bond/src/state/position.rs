@@ -1,4 +1,5 @@
-use crate::com::*;
+use crate::decimal::Decimal;
+use crate::errors::BondError;
 use crate::state::market;
 use anchor_lang::prelude::*;
 use num_enum::TryFromPrimitive;
@@ -8,7 +9,7 @@ use serde::{Deserialize, Serialize};
 pub struct Position {
     pub position_seed_offset: u32,
     /// Initial position margin
-    pub margin: f64,
+    pub margin: Decimal,
     /// leverage size
     pub leverage: u16,
     /// 1 full position mode, 2 independent position modes.
@@ -18,27 +19,27 @@ pub struct Position {
     /// 1 buy long, 2 sell short.
     pub direction: Direction,
     /// the position size
-    pub size: f64,
+    pub size: Decimal,
     /// default is 1,Reserved in the future
     pub lot: u64,
     // Opening quotation (expected opening price under the listing mode)
-    pub open_price: f64,
+    pub open_price: Decimal,
     /// Point difference data on which the quotation is based
-    pub open_spread: f64,
+    pub open_spread: Decimal,
     // Actual quotation currently obtained
-    pub open_real_price: f64,
+    pub open_real_price: Decimal,
     /// Closing quotation
-    pub close_price: f64,
+    pub close_price: Decimal,
     /// Point difference data on which the quotation is based
-    pub close_spread: f64,
+    pub close_spread: Decimal,
     // Actual quotation currently obtained
-    pub close_real_price: f64,
+    pub close_real_price: Decimal,
     // PL
-    pub profit: f64,
+    pub profit: Decimal,
     /// Automatic profit stop price
-    pub stop_surplus_price: f64,
+    pub stop_surplus_price: Decimal,
     /// Automatic stop loss price
-    pub stop_loss_price: f64,
+    pub stop_loss_price: Decimal,
     /// Order creation time
     pub create_time: i64,
     pub open_time: i64,
@@ -55,6 +56,14 @@ pub struct Position {
     pub authority: Pubkey,
     /// Market account number of the position
     pub market_account: Pubkey,
+    /// Cumulative funding index of the market side when the position was opened
+    pub funding_index: Decimal,
+    /// Transaction fee charged when the position was opened
+    pub open_fee: Decimal,
+    /// Transaction fee charged when the position was closed
+    pub close_fee: Decimal,
+    /// Management fee charged for the holding time
+    pub management_fee: Decimal,
 }
 
 #[derive(
@@ -113,41 +122,128 @@ pub enum Direction {
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, Deserialize, Serialize)]
 pub struct PositionHeader {
     pub position_seed_offset: u32,
-    pub open_price: f64,
+    pub open_price: Decimal,
     pub direction: Direction,
-    pub size: f64,
-    pub margin: f64,
-    pub market: FullPositionMarket,
+    pub size: Decimal,
+    pub margin: Decimal,
+    /// Full position id of the market, see `Market::full_position_id`
+    pub market_id: u16,
+    pub funding_index: Decimal,
+    pub open_time: i64,
 }
 
 impl PositionHeader {
-    pub const LEN: usize = 4 + 8 + (1 + 1) + 8 + 8 + (1 + 1);
+    pub const LEN: usize = 4 + 8 + (1 + 1) + 8 + 8 + 2 + 8 + 8;
     // Floating P/L
-    pub fn get_pl_price(&self, p: &market::Price) -> f64 {
+    pub fn get_pl_price(&self, p: &market::Price) -> Result<Decimal> {
         match self.direction {
-            Direction::Buy => (p.sell_price - self.open_price) * self.size,
-            Direction::Sell => (self.open_price - p.buy_price) * self.size,
+            Direction::Buy => p.sell_price.checked_sub(self.open_price)?,
+            Direction::Sell => self.open_price.checked_sub(p.buy_price)?,
         }
+        .checked_mul(self.size)
     }
-    pub fn get_fund_size(&self) -> f64 {
-        self.open_price * self.size
+    pub fn get_fund_size(&self) -> Result<Decimal> {
+        self.open_price.checked_mul(self.size)
     }
 }
 
 impl Position {
-    pub const LEN: usize = 4 + 8 + 2 + (1 + 1) * 3 + 8 * 15 + 32 * 4;
+    pub const LEN: usize = 4 + 8 + 2 + (1 + 1) * 3 + 8 * 15 + 32 * 4 + 8 * 4;
     // Floating P/L
-    pub fn get_pl_price(&self, p: &market::Price) -> f64 {
+    pub fn get_pl_price(&self, p: &market::Price) -> Result<Decimal> {
+        self.get_pl_price_of(p, self.size)
+    }
+    /// Floating P/L of `size` of the position
+    pub fn get_pl_price_of(&self, p: &market::Price, size: Decimal) -> Result<Decimal> {
+        match self.direction {
+            Direction::Buy => p.sell_price.checked_sub(self.open_price)?,
+            Direction::Sell => self.open_price.checked_sub(p.buy_price)?,
+        }
+        .checked_mul_int(self.lot as i64)?
+        .checked_mul(size)
+    }
+    pub fn get_fund_size(&self) -> Result<Decimal> {
+        self.get_fund_size_of(self.size)
+    }
+    /// Fund size of `size` of the position
+    pub fn get_fund_size_of(&self, size: Decimal) -> Result<Decimal> {
+        self.open_price
+            .checked_mul_int(self.lot as i64)?
+            .checked_mul(size)
+    }
+    /// Leverage implied by the current margin, rounded up
+    pub fn get_effective_leverage(&self) -> Result<u16> {
+        let leverage = self.get_fund_size()?.checked_div(self.margin)?.raw();
+        let leverage = leverage / Decimal::SCALE + (leverage % Decimal::SCALE != 0) as i64;
+        u16::try_from(leverage.max(1)).map_err(|_| BondError::MathOverflow.into())
+    }
+    /// Close quote at which the equity ratio of an independent position reaches
+    /// `burst_rate`, funding and fees aside
+    pub fn get_liquidation_price(&self, burst_rate: Decimal) -> Result<Decimal> {
+        let distance = self
+            .margin
+            .checked_mul(Decimal::ONE.checked_sub(burst_rate)?)?
+            .checked_div(self.size)?;
         match self.direction {
-            Direction::Buy => {
-                f64_round((p.sell_price - self.open_price) * self.lot as f64 * self.size)
-            }
-            Direction::Sell => {
-                f64_round((self.open_price - p.buy_price) * self.lot as f64 * self.size)
-            }
+            Direction::Buy => Ok(self.open_price.checked_sub(distance)?.max(Decimal::ZERO)),
+            Direction::Sell => self.open_price.checked_add(distance),
+        }
+    }
+    /// Summary of the position kept in the user account and used to value it
+    pub fn get_header(&self, market_id: u16) -> PositionHeader {
+        PositionHeader {
+            position_seed_offset: self.position_seed_offset,
+            open_price: self.open_price,
+            direction: self.direction,
+            size: self.size,
+            margin: self.margin,
+            market_id,
+            funding_index: self.funding_index,
+            open_time: self.open_time,
+        }
+    }
+    // the quote the position is closed at
+    pub fn get_close_price(&self, p: &market::Price) -> Decimal {
+        match self.direction {
+            Direction::Buy => p.sell_price,
+            Direction::Sell => p.buy_price,
+        }
+    }
+    /// Triggers must sit on the profit and loss side of the current close price,
+    /// zero disables a trigger.
+    pub fn check_triggers(&self, p: &market::Price) -> Result<()> {
+        let close_price = self.get_close_price(p);
+        let (surplus_valid, loss_valid) = match self.direction {
+            Direction::Buy => (
+                self.stop_surplus_price > close_price,
+                self.stop_loss_price < close_price,
+            ),
+            Direction::Sell => (
+                self.stop_surplus_price < close_price,
+                self.stop_loss_price > close_price,
+            ),
+        };
+        if (!self.stop_surplus_price.is_zero() && !surplus_valid)
+            || (!self.stop_loss_price.is_zero() && !loss_valid)
+        {
+            return Err(BondError::InvalidTriggerPrice.into());
         }
+        Ok(())
     }
-    pub fn get_fund_size(&self) -> f64 {
-        self.open_price * self.lot as f64 * self.size
+    /// The close price crossed the stop surplus or the stop loss price
+    pub fn is_trigger_reached(&self, p: &market::Price) -> bool {
+        let close_price = self.get_close_price(p);
+        let (surplus_reached, loss_reached) = match self.direction {
+            Direction::Buy => (
+                close_price >= self.stop_surplus_price,
+                close_price <= self.stop_loss_price,
+            ),
+            Direction::Sell => (
+                close_price <= self.stop_surplus_price,
+                close_price >= self.stop_loss_price,
+            ),
+        };
+        (!self.stop_surplus_price.is_zero() && surplus_reached)
+            || (!self.stop_loss_price.is_zero() && loss_reached)
     }
 }
@@ -1,3 +1,4 @@
+use crate::decimal::Decimal;
 use crate::errors::BondError;
 use crate::state::position::*;
 use anchor_lang::prelude::*;
@@ -13,19 +14,19 @@ pub struct UserAccount {
     pub position_seed_offset: u32,
     /// Balance of user account (maintain the deposit,
     ///  and the balance here will be deducted when the deposit used in the full position mode is deducted)
-    pub balance: f64,
+    pub balance: Decimal,
     /// User settled profit
-    pub profit: f64,
+    pub profit: Decimal,
     /// Total amount of margin used.
-    pub margin_total: f64,
+    pub margin_total: Decimal,
     /// Total amount of used margin in full warehouse mode.
-    pub margin_full_total: f64,
+    pub margin_full_total: Decimal,
     /// Total amount of used margin in independent position mode.
-    pub margin_independent_total: f64,
-    pub margin_full_buy_total: f64,
-    pub margin_full_sell_total: f64,
-    pub margin_independent_buy_total: f64,
-    pub margin_independent_sell_total: f64,
+    pub margin_independent_total: Decimal,
+    pub margin_full_buy_total: Decimal,
+    pub margin_full_sell_total: Decimal,
+    pub margin_independent_buy_total: Decimal,
+    pub margin_independent_sell_total: Decimal,
     pub position_full_vector: i32,
     /// space for future derived values
     pub drv1: u8,
@@ -46,7 +47,7 @@ pub struct UserAccount {
 /// You can only keep so many order indexes at most.
 /// To view all orders, you need to traverse from the beginning
 /// We are still determining the range of this value depending on the node calculation force and use cost
-pub const MAX_INDEX_SIZE: usize = 861;
+pub const MAX_INDEX_SIZE: usize = 661;
 /// Number of full warehouses allowed to be opened
 /// We are still determining the range of this value depending on the node calculation force and use cost
 pub const MAX_OPEN_FULL_POSITION_SET_SIZE: usize = 100;
@@ -54,8 +55,9 @@ pub const MAX_OPEN_FULL_POSITION_SET_SIZE: usize = 100;
 impl UserAccount {
     /// MAX_INDEX_SIZE=x
     /// MAX_OPEN_FULL_POSITION_SET_SIZE=y
-    /// 8+127+2(4+4x)+(4+32y)=1024*10
-    /// 8x+24y=10093
+    /// PositionHeader::LEN=48
+    /// 8+127+2(4+4x)+(4+48y)<=1024*10
+    /// 8x+48y<=10093, y=100 gives x=661
     pub const LEN: usize = 32
         + 4
         + 8 * 9
@@ -64,6 +66,59 @@ impl UserAccount {
         + (4 + 4 * MAX_INDEX_SIZE) * 2
         + (4 + PositionHeader::LEN * MAX_OPEN_FULL_POSITION_SET_SIZE);
 
+    /// Margin of the full positions used for the liquidation line,
+    /// opposite positions hedge each other so only the larger side counts.
+    pub fn get_full_position_margin(&self) -> Decimal {
+        self.margin_full_buy_total.max(self.margin_full_sell_total)
+    }
+    /// Add the margin of a new position to the margin totals
+    pub fn lock_margin(
+        &mut self,
+        position_type: &PositionType,
+        direction: Direction,
+        margin: Decimal,
+    ) -> Result<()> {
+        self.margin_total = self.margin_total.checked_add(margin)?;
+        match position_type {
+            PositionType::Full => {
+                self.margin_full_total = self.margin_full_total.checked_add(margin)?;
+                match direction {
+                    Direction::Buy => {
+                        self.margin_full_buy_total =
+                            self.margin_full_buy_total.checked_add(margin)?
+                    }
+                    Direction::Sell => {
+                        self.margin_full_sell_total =
+                            self.margin_full_sell_total.checked_add(margin)?
+                    }
+                }
+            }
+            PositionType::Independent => {
+                self.margin_independent_total =
+                    self.margin_independent_total.checked_add(margin)?;
+                match direction {
+                    Direction::Buy => {
+                        self.margin_independent_buy_total =
+                            self.margin_independent_buy_total.checked_add(margin)?
+                    }
+                    Direction::Sell => {
+                        self.margin_independent_sell_total =
+                            self.margin_independent_sell_total.checked_add(margin)?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Remove the margin of a closed position from the margin totals
+    pub fn release_margin(
+        &mut self,
+        position_type: &PositionType,
+        direction: Direction,
+        margin: Decimal,
+    ) -> Result<()> {
+        self.lock_margin(position_type, direction, margin.checked_neg()?)
+    }
     pub fn update_index_by_close(&mut self, offset: u32) {
         if offset <= 0 {
             return;
@@ -92,6 +147,16 @@ impl UserAccount {
         self.open_full_position_headers.push(h);
         Ok(())
     }
+    /// Replace the header of a position whose size changed
+    pub fn update_position_header(&mut self, h: PositionHeader) {
+        if let Some(x) = self
+            .open_full_position_headers
+            .iter_mut()
+            .find(|x| x.position_seed_offset == h.position_seed_offset)
+        {
+            *x = h;
+        }
+    }
     pub fn remove_position_header(&mut self, h: PositionHeader) {
         self.open_full_position_headers
             .retain(|x| x.position_seed_offset != h.position_seed_offset);
@@ -0,0 +1,101 @@
+use anchor_lang::prelude::*;
+declare_id!("FPMtauEiEwwgJ11SS1ehHx6V27MLWnHwkZKQLCdrk1EU");
+
+pub const MOCK_PRICE_ACCOUNT_SEED: &[u8] = b"scale_mock_price_account";
+
+/// Scriptable price feeds read by the bond program in place of pyth when it is built
+/// with the `mock-oracle` feature. Only deployed on test clusters.
+#[program]
+pub mod mock_oracle {
+    use super::*;
+    /// Create the mock feed of a pair, the payer becomes its authority
+    pub fn initialize_mock_price(ctx: Context<InitializeMockPrice>, pair: String) -> Result<()> {
+        if pair.len() > 20 {
+            return err!(MockOracleError::PairTooLong);
+        }
+        let mock_price_account = &mut ctx.accounts.mock_price_account;
+        mock_price_account.authority = ctx.accounts.authority.key();
+        mock_price_account.pair = pair;
+        Ok(())
+    }
+    /// Script the quote `price * 10^expo` of a feed, by its authority.
+    /// A zero `publish_time` means the current cluster time.
+    pub fn set_mock_price(
+        ctx: Context<SetMockPrice>,
+        price: i64,
+        conf: u64,
+        expo: i32,
+        publish_time: i64,
+    ) -> Result<()> {
+        let mock_price_account = &mut ctx.accounts.mock_price_account;
+        mock_price_account.price = price;
+        mock_price_account.conf = conf;
+        mock_price_account.expo = expo;
+        mock_price_account.publish_time = if publish_time == 0 {
+            Clock::get()?.unix_timestamp
+        } else {
+            publish_time
+        };
+        msg!(
+            "set mock price pair:{:?},price:{},conf:{},expo:{},publish_time:{}",
+            mock_price_account.pair,
+            price,
+            conf,
+            expo,
+            mock_price_account.publish_time
+        );
+        Ok(())
+    }
+}
+
+/// A pyth-like quote `price * 10^expo` with its confidence interval.
+#[account]
+#[derive(Debug)]
+pub struct MockPrice {
+    pub authority: Pubkey,
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_time: i64,
+    /// Transaction pair (token type, such as BTC, ETH)
+    /// len: 4+20
+    pub pair: String,
+}
+
+impl MockPrice {
+    pub const LEN: usize = 32 + 8 + 8 + 4 + 8 + (4 + 20);
+}
+
+#[derive(Accounts)]
+#[instruction(pair: String)]
+pub struct InitializeMockPrice<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        init,
+        payer=authority,
+        space=MockPrice::LEN + 8,
+        seeds = [MOCK_PRICE_ACCOUNT_SEED,pair.as_bytes()],
+        bump,
+    )]
+    pub mock_price_account: Account<'info, MockPrice>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetMockPrice<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority@MockOracleError::NoPermission,
+    )]
+    pub mock_price_account: Account<'info, MockPrice>,
+}
+
+#[error_code]
+pub enum MockOracleError {
+    #[msg("No permission")]
+    NoPermission,
+    #[msg("The pair is too long")]
+    PairTooLong,
+}
@@ -1,6 +1,6 @@
 import * as anchor from "@project-serum/anchor";
 import { Program } from "@project-serum/anchor";
-import { getAccount, transfer } from "@solana/spl-token";
+import { getAccount, getOrCreateAssociatedTokenAccount, transfer } from "@solana/spl-token";
 import { PublicKey, } from '@solana/web3.js'
 import { Bond } from "../target/types/bond";
 const BN = anchor.BN;
@@ -25,6 +25,20 @@ const MARKET_ACCOUNT_SEED = encode("scale_market_account");
 
 const POSITION_ACCOUNT_SEED = encode("scale_position_account");
 
+const CONFIG_ACCOUNT_SEED = encode("scale_config_account");
+
+const LP_MINT_SEED = encode("scale_lp_mint");
+
+const INVESTOR_ACCOUNT_SEED = encode("scale_investor_account");
+
+const BOND_ACCOUNT_SEED = encode("scale_bond_account");
+
+const BOND_ESCROW_SEED = encode("scale_bond_escrow");
+
+const METADATA_PROGRAM_ID = new PublicKey("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new PublicKey("BPFLoaderUpgradeab1e11111111111111111111111");
+
 const VAULT_ACCOUNT = "F7NPLGunbG5rmKnYY7opt1SWfgNXRg8LUyoi4LK7wpu4"
 
 
@@ -78,11 +92,27 @@ describe("bond", () => {
 
   const program = anchor.workspace.Bond as Program<Bond>;
   var SPL;
+  const [config_account, _config_bump] = PublicKey.findProgramAddressSync(
+    [CONFIG_ACCOUNT_SEED],
+    program.programId)
 
   // let mint = new PublicKey("Bu91vdLYSmiip8fS7ijzTcFAnu3TNCUA7kfj2pRMzC9T");
   it("test vault account init", async () => {
     let SPL_ACCOUNT = await initSplAccounts({ provider: provider })
     SPL = SPL_ACCOUNT
+    const [program_data, _] = PublicKey.findProgramAddressSync(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_PROGRAM_ID)
+    await program.methods.initializeConfig({
+      teamAuthority: provider.wallet.publicKey,
+      projectFundWallet: provider.wallet.publicKey,
+      clearingRobot: provider.wallet.publicKey,
+    }).accounts({
+      configAccount: config_account,
+      vaultMint: SPL_ACCOUNT.mint,
+      program: program.programId,
+      programData: program_data,
+    }).rpc()
     let [usdcVault, usdcBump] = await PublicKey.findProgramAddress(
       [
         VAULT_TOKEN_ACCOUNT_SEED,
@@ -92,6 +122,7 @@ describe("bond", () => {
     );
     // Add your test here.
     var tx = await program.methods.initializeVault(usdcBump).accounts({
+      configAccount: config_account,
       tokenMint: SPL_ACCOUNT.mint,
       vaultAccount: usdcVault,
     }).signers([]).rpc();
@@ -133,12 +164,13 @@ describe("bond", () => {
       program.programId)
     var tx = await program.methods.initializeMarket(
       PAIR.BTC,
-      0.01,
+      new BN(10000),
       bump,
       PYTH_PRICE.DEVNET.BTC,
       CHAINLINK_PRICE.DEVNET.BTC,
     ).accounts({
-      marketAccount: market_account_btc
+      configAccount: config_account,
+      marketAccount: market_account_btc,
     }).rpc()
     console.log("tx:", tx, "market_account_btc:", market_account_btc.toBase58())
     var account = await program.account.market.fetch(market_account_btc)
@@ -151,12 +183,13 @@ describe("bond", () => {
       program.programId)
     var tx = await program.methods.initializeMarket(
       PAIR.ETH,
-      0.01,
+      new BN(10000),
       e_bump,
       PYTH_PRICE.DEVNET.ETH,
       CHAINLINK_PRICE.DEVNET.ETH,
     ).accounts({
-      marketAccount: market_account_eth
+      configAccount: config_account,
+      marketAccount: market_account_eth,
     }).rpc()
     console.log("tx:", tx, "market_account_eth:", market_account_eth.toBase58())
 
@@ -166,29 +199,137 @@ describe("bond", () => {
       program.programId)
     var tx = await program.methods.initializeMarket(
       PAIR.SOL,
-      0.01,
+      new BN(10000),
       s_bump,
       PYTH_PRICE.DEVNET.SOL,
       CHAINLINK_PRICE.DEVNET.SOL,
     ).accounts({
-      marketAccount: market_account_sol
+      configAccount: config_account,
+      marketAccount: market_account_sol,
     }).rpc()
     console.log("tx:", tx, "market_account_sol:", market_account_sol.toBase58())
   });
 
+  it("test update oracle config", async () => {
+    let [market_account, _bump] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    var tx = await program.methods.updateOracleConfig(
+      PAIR.BTC,
+      new BN(10000),
+      new BN(60),
+      new BN(20000),
+    ).accounts({
+      marketAccount: market_account,
+    }).rpc()
+    var account = await program.account.market.fetch(market_account)
+    assert.strictEqual(account.maxPriceDivergence.value.toNumber(), 10000);
+    assert.strictEqual(account.maxPriceAge.toNumber(), 60);
+  });
+
+  it("test set market status", async () => {
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    var tx = await program.methods.setMarketStatus(PAIR.BTC, 2).accounts({
+      marketAccount: market_account_btc,
+    }).rpc()
+    var market = await program.account.market.fetch(market_account_btc)
+    assert.deepEqual(market.status, { locked: {} });
+    tx = await program.methods.setMarketStatus(PAIR.BTC, 1).accounts({
+      marketAccount: market_account_btc,
+    }).rpc()
+    market = await program.account.market.fetch(market_account_btc)
+    assert.deepEqual(market.status, { normal: {} });
+  });
+
+  it("test set leverage tiers", async () => {
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    var tx = await program.methods.setLeverageTiers(PAIR.BTC, [
+      { minFundSize: { value: new BN(100000000000) }, maxLeverage: 50 },
+      { minFundSize: { value: new BN(1000000000000) }, maxLeverage: 20 },
+    ]).accounts({
+      marketAccount: market_account_btc,
+    }).rpc()
+    const market = await program.account.market.fetch(market_account_btc)
+    assert.strictEqual(market.leverageTiers[0].maxLeverage, 50);
+    assert.strictEqual(market.leverageTiers[1].maxLeverage, 20);
+    assert.strictEqual(market.leverageTiers[2].maxLeverage, 0);
+  });
+
+  it("test update risk params", async () => {
+    let [market_account_sol, _s] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.SOL)],
+      program.programId)
+    var tx = await program.methods.updateRiskParams(PAIR.SOL, {
+      positionDiffProportion: { value: new BN(500000) },
+      positionProportion: { value: new BN(1000000) },
+      positionProportionOne: { value: new BN(100000) },
+      fundRate: { value: new BN(20000) },
+      burstRate: { value: new BN(600000) },
+    }).accounts({
+      marketAccount: market_account_sol,
+    }).rpc()
+    const market = await program.account.market.fetch(market_account_sol)
+    assert.strictEqual(market.riskParams.burstRate.value.toNumber(), 600000);
+  });
+
+  it("test add full position market", async () => {
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    var tx = await program.methods.addFullPositionMarket(PAIR.BTC).accounts({
+      configAccount: config_account,
+      marketAccount: market_account_btc,
+    }).rpc()
+    const market = await program.account.market.fetch(market_account_btc)
+    assert.strictEqual(market.isSupportFullPosition, true);
+    assert.strictEqual(market.fullPositionId, 1);
+    const config = await program.account.config.fetch(config_account)
+    assert.strictEqual(config.fullPositionMarkets[0].toBase58(), market_account_btc.toBase58());
+  });
+
   it("test market account investment", async () => {
     let [market_account, _bump] = await PublicKey.findProgramAddress(
       [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
       program.programId)
-    var tx = await program.methods.investment(
+    let [lp_mint, _lp_bump] = await PublicKey.findProgramAddress(
+      [LP_MINT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    let [vault_pda, _pda_bump] = await PublicKey.findProgramAddress([VAULT_TOKEN_AUTHORITY_SEED], program.programId)
+    var tx = await program.methods.initializeLpMint(PAIR.BTC).accounts({
+      configAccount: config_account,
+      tokenMint: SPL.mint,
+      marketAccount: market_account,
+      lpMint: lp_mint,
+      pdaAuthorityAccount: vault_pda,
+    }).rpc()
+    const userLpTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection, provider.wallet.payer, lp_mint, provider.wallet.publicKey)
+    let [investor_account, _i_bump] = await PublicKey.findProgramAddress(
+      [INVESTOR_ACCOUNT_SEED, provider.wallet.publicKey.toBytes(), market_account.toBytes()],
+      program.programId)
+    tx = await program.methods.investment(
       PAIR.BTC,
       new BN(10000)
     ).accounts({
+      configAccount: config_account,
       tokenMint: SPL.mint,
       userTokenAccount: SPL.userTokenAccount.address,
       vaultTokenAccount: new PublicKey(VAULT_ACCOUNT),
       marketAccount: market_account,
+      lpMint: lp_mint,
+      userLpTokenAccount: userLpTokenAccount.address,
+      pdaAuthorityAccount: vault_pda,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+      investorAccount: investor_account,
     }).rpc()
+    // the first liquidity is priced one share per token
+    const lpAccount = await getAccount(provider.connection, userLpTokenAccount.address);
+    assert.strictEqual(lpAccount.amount, BigInt(10000));
     const account = await getAccount(provider.connection, SPL.userTokenAccount.address);
     console.log("user_token_account amount:", account.amount);
     assert.strictEqual(account.amount, BigInt(40000));
@@ -203,17 +344,77 @@ describe("bond", () => {
       [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
       program.programId)
 
+    let [lp_mint, _lp_bump] = await PublicKey.findProgramAddress(
+      [LP_MINT_SEED, encode(PAIR.BTC)],
+      program.programId)
     let [vault_pda, _pda_bump] = await PublicKey.findProgramAddress([VAULT_TOKEN_AUTHORITY_SEED], program.programId)
-    var tx = await program.methods.divestment(
-      PAIR.BTC,
-      new BN(1000)
-    ).accounts({
+    const userLpTokenAccount = await getOrCreateAssociatedTokenAccount(
+      provider.connection, provider.wallet.payer, lp_mint, provider.wallet.publicKey)
+    let [investor_account, _i_bump] = await PublicKey.findProgramAddress(
+      [INVESTOR_ACCOUNT_SEED, provider.wallet.publicKey.toBytes(), market_account.toBytes()],
+      program.programId)
+    // the request waits for the cooldown of the market
+    var tx = await program.methods.updateDivestmentConfig(PAIR.BTC, new BN(3600)).accounts({
+      marketAccount: market_account,
+    }).rpc()
+    const divestmentAccounts = {
+      configAccount: config_account,
       tokenMint: SPL.mint,
       userTokenAccount: SPL.userTokenAccount.address,
       vaultTokenAccount: new PublicKey(VAULT_ACCOUNT),
       pdaAuthorityAccount: vault_pda,
       marketAccount: market_account,
+      lpMint: lp_mint,
+      userLpTokenAccount: userLpTokenAccount.address,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+      investorAccount: investor_account,
+    }
+    const requestAccounts = {
+      marketAccount: market_account,
+      lpMint: lp_mint,
+      userLpTokenAccount: userLpTokenAccount.address,
+      investorAccount: investor_account,
+    }
+    // redemption is limited by the LP tokens held
+    try {
+      await program.methods.requestDivestment(PAIR.BTC, new BN(10001)).accounts(requestAccounts).rpc()
+      assert.fail("the request should not exceed the LP tokens held")
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal("InsufficientShares")
+    }
+    tx = await program.methods.requestDivestment(PAIR.BTC, new BN(1000)).accounts(requestAccounts).rpc()
+    try {
+      await program.methods.executeDivestment(PAIR.BTC).accounts(divestmentAccounts).rpc()
+      assert.fail("the divestment should wait for the cooldown")
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal("DivestmentCooldown")
+    }
+    // lowering the cooldown does not apply to the pending request
+    tx = await program.methods.updateDivestmentConfig(PAIR.BTC, new BN(0)).accounts({
+      marketAccount: market_account,
     }).rpc()
+    try {
+      await program.methods.executeDivestment(PAIR.BTC).accounts(divestmentAccounts).rpc()
+      assert.fail("the pending request keeps its cooldown")
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal("DivestmentCooldown")
+    }
+    tx = await program.methods.requestDivestment(PAIR.BTC, new BN(1000)).accounts(requestAccounts).rpc()
+    tx = await program.methods.executeDivestment(PAIR.BTC).accounts(divestmentAccounts).rpc()
+    const lpAccount = await getAccount(provider.connection, userLpTokenAccount.address)
+    assert.strictEqual(lpAccount.amount, BigInt(10000 - 1000))
+    const investor = await program.account.investorAccount.fetch(investor_account)
+    assert.strictEqual(investor.shares.value.toNumber(), 9000);
+    assert.strictEqual(investor.principal.value.toNumber(), 9000);
+    const investorYield = await program.methods.getInvestorYield(PAIR.BTC).accounts({
+      marketAccount: market_account,
+      lpMint: lp_mint,
+      investorAccount: investor_account,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+    }).view()
+    assert.strictEqual(investorYield.value.value.toNumber(), 9000);
     const account = await getAccount(provider.connection, SPL.userTokenAccount.address)
     console.log("user_token_account amount:", account.amount)
     assert.strictEqual(account.amount, BigInt(40000 + 1000));
@@ -223,6 +424,85 @@ describe("bond", () => {
     console.log("vault_account_amount:", vault_account_data.amount)
   });
 
+  it("test bond investment and divestment", async () => {
+    let [market_account, _bump] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    let [lp_mint, _lp_bump] = await PublicKey.findProgramAddress(
+      [LP_MINT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    let [vault_pda, _pda_bump] = await PublicKey.findProgramAddress([VAULT_TOKEN_AUTHORITY_SEED], program.programId)
+    let [bond_escrow_account, _e_bump] = await PublicKey.findProgramAddress(
+      [BOND_ESCROW_SEED, encode(PAIR.BTC)],
+      program.programId)
+    // 10% boost per year of lock term
+    var tx = await program.methods.updateBondConfig(PAIR.BTC, new BN(100000)).accounts({
+      marketAccount: market_account,
+    }).rpc()
+    const bondMint = anchor.web3.Keypair.generate();
+    let [bond_account, _b_bump] = await PublicKey.findProgramAddress(
+      [BOND_ACCOUNT_SEED, bondMint.publicKey.toBytes()],
+      program.programId)
+    let [metadata_account, _m_bump] = await PublicKey.findProgramAddress(
+      [encode("metadata"), METADATA_PROGRAM_ID.toBytes(), bondMint.publicKey.toBytes()],
+      METADATA_PROGRAM_ID)
+    const userBondTokenAccount = await anchor.utils.token.associatedAddress({
+      mint: bondMint.publicKey,
+      owner: provider.wallet.publicKey,
+    })
+    tx = await program.methods.bondInvestment(
+      PAIR.BTC,
+      new BN(1000),
+      new BN(1),
+      "https://scale.exchange/bond.json"
+    ).accounts({
+      configAccount: config_account,
+      tokenMint: SPL.mint,
+      userTokenAccount: SPL.userTokenAccount.address,
+      vaultTokenAccount: new PublicKey(VAULT_ACCOUNT),
+      marketAccount: market_account,
+      lpMint: lp_mint,
+      bondEscrowAccount: bond_escrow_account,
+      bondMint: bondMint.publicKey,
+      userBondTokenAccount: userBondTokenAccount,
+      bondAccount: bond_account,
+      metadataAccount: metadata_account,
+      pdaAuthorityAccount: vault_pda,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+      metadataProgram: METADATA_PROGRAM_ID,
+    }).signers([bondMint]).rpc()
+    const nft = await getAccount(provider.connection, userBondTokenAccount)
+    assert.strictEqual(nft.amount, BigInt(1))
+    const bond = await program.account.bond.fetch(bond_account)
+    assert.strictEqual(bond.principal.value.toNumber(), 1000);
+    assert.strictEqual(bond.shares.value.toNumber(), 1000);
+    const escrow = await getAccount(provider.connection, bond_escrow_account)
+    assert.strictEqual(escrow.amount, BigInt(1000))
+
+    // wait for the end of the lock term
+    await new Promise(resolve => setTimeout(resolve, 2000));
+    tx = await program.methods.bondDivestment(PAIR.BTC).accounts({
+      configAccount: config_account,
+      tokenMint: SPL.mint,
+      holderTokenAccount: SPL.userTokenAccount.address,
+      vaultTokenAccount: new PublicKey(VAULT_ACCOUNT),
+      pdaAuthorityAccount: vault_pda,
+      marketAccount: market_account,
+      lpMint: lp_mint,
+      bondEscrowAccount: bond_escrow_account,
+      bondMint: bondMint.publicKey,
+      holderBondTokenAccount: userBondTokenAccount,
+      bondAccount: bond_account,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+    }).rpc()
+    const burned = await getAccount(provider.connection, userBondTokenAccount)
+    assert.strictEqual(burned.amount, BigInt(0))
+    const account = await getAccount(provider.connection, SPL.userTokenAccount.address)
+    assert.strictEqual(account.amount, BigInt(40000 + 1000));
+  });
+
   it("test user account init", async () => {
     let [user_account, bump] = await PublicKey.findProgramAddress(
       [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
@@ -254,6 +534,7 @@ describe("bond", () => {
     )
 
     const tx = await program.methods.deposit(new BN(1000)).accounts({
+      configAccount: config_account,
       tokenMint: SPL.mint,
       userTokenAccount: SPL.userTokenAccount.address,
       userAccount: user_account,
@@ -269,18 +550,37 @@ describe("bond", () => {
     console.log("vault_account_amount:", vault_account.amount)
   });
 
-  it("test open position", async () => {
+  it("test withdraw", async () => {
     let [user_account, _a] = await PublicKey.findProgramAddress(
       [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
       program.programId)
     let [market_account_btc, _b] = await PublicKey.findProgramAddress(
       [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
       program.programId)
-    let [market_account_eth, _e] = await PublicKey.findProgramAddress(
-      [MARKET_ACCOUNT_SEED, encode(PAIR.ETH)],
+    let [vault_pda, _pda_bump] = await PublicKey.findProgramAddress([VAULT_TOKEN_AUTHORITY_SEED], program.programId)
+    const before = await program.account.userAccount.fetch(user_account)
+    var tx = await program.methods.withdraw(new BN(100)).accounts({
+      configAccount: config_account,
+      tokenMint: SPL.mint,
+      userTokenAccount: SPL.userTokenAccount.address,
+      userAccount: user_account,
+      vaultTokenAccount: new PublicKey(VAULT_ACCOUNT),
+      pdaAuthorityAccount: vault_pda,
+    }).remainingAccounts([
+      { pubkey: market_account_btc, isWritable: false, isSigner: false },
+      { pubkey: new PublicKey(PYTH_PRICE.DEVNET.BTC), isWritable: false, isSigner: false },
+      { pubkey: new PublicKey(CHAINLINK_PRICE.DEVNET.BTC), isWritable: false, isSigner: false },
+    ]).rpc()
+    const account = await program.account.userAccount.fetch(user_account)
+    assert.strictEqual(account.balance.value.toNumber(), before.balance.value.toNumber() - 100);
+  });
+
+  it("test open position", async () => {
+    let [user_account, _a] = await PublicKey.findProgramAddress(
+      [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
       program.programId)
-    let [market_account_sol, _s] = await PublicKey.findProgramAddress(
-      [MARKET_ACCOUNT_SEED, encode(PAIR.SOL)],
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
       program.programId)
     let [position_account, _c] = await PublicKey.findProgramAddress(
       [
@@ -293,7 +593,7 @@ describe("bond", () => {
     )
     var tx = await program.methods.openPosition(
       PAIR.BTC,
-      2.3,
+      new BN(2300000),
       4,
       1,
       1,
@@ -303,15 +603,212 @@ describe("bond", () => {
       positionAccount: position_account,
       pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
       chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
-      marketAccountBtc: market_account_btc,
-      marketAccountEth: market_account_eth,
-      marketAccountSol: market_account_sol,
-      pythPriceAccountBtc: PYTH_PRICE.DEVNET.BTC,
-      pythPriceAccountEth: PYTH_PRICE.DEVNET.ETH,
-      pythPriceAccountSol: PYTH_PRICE.DEVNET.SOL,
-      chainlinkPriceAccountBtc: CHAINLINK_PRICE.DEVNET.BTC,
-      chainlinkPriceAccountEth: CHAINLINK_PRICE.DEVNET.ETH,
-      chainlinkPriceAccountSol: CHAINLINK_PRICE.DEVNET.SOL,
+    }).remainingAccounts([
+      { pubkey: market_account_btc, isWritable: false, isSigner: false },
+      { pubkey: new PublicKey(PYTH_PRICE.DEVNET.BTC), isWritable: false, isSigner: false },
+      { pubkey: new PublicKey(CHAINLINK_PRICE.DEVNET.BTC), isWritable: false, isSigner: false },
+    ]).rpc()
+  });
+
+  it("test reduce position", async () => {
+    let [user_account, _a] = await PublicKey.findProgramAddress(
+      [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
+      program.programId)
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    let [position_account, _c] = await PublicKey.findProgramAddress(
+      [
+        POSITION_ACCOUNT_SEED,
+        provider.wallet.publicKey.toBytes(),
+        user_account.toBytes(),
+        encode("1")
+      ],
+      program.programId,
+    )
+    const before = await program.account.position.fetch(position_account)
+    var tx = await program.methods.reducePosition(new BN(1000000)).accounts({
+      configAccount: config_account,
+      userAccount: user_account,
+      marketAccount: market_account_btc,
+      positionAccount: position_account,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+    }).rpc()
+    const position = await program.account.position.fetch(position_account)
+    assert.deepEqual(position.positionStatus, { normal: {} });
+    assert.strictEqual(position.size.value.toNumber(), 1300000);
+    assert.isBelow(position.margin.value.toNumber(), before.margin.value.toNumber());
+    const account = await program.account.userAccount.fetch(user_account)
+    assert.strictEqual(account.openFullPositionHeaders[0].size.value.toNumber(), 1300000);
+    assert.strictEqual(account.marginFullTotal.value.toNumber(), position.margin.value.toNumber());
+  });
+
+  it("test liquidate account rejects a duplicate position", async () => {
+    let [user_account, _a] = await PublicKey.findProgramAddress(
+      [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
+      program.programId)
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    let [position_account, _c] = await PublicKey.findProgramAddress(
+      [
+        POSITION_ACCOUNT_SEED,
+        provider.wallet.publicKey.toBytes(),
+        user_account.toBytes(),
+        encode("1")
+      ],
+      program.programId,
+    )
+    try {
+      await program.methods.liquidateAccount(1).accounts({
+        userAccount: user_account,
+      }).remainingAccounts([
+        { pubkey: market_account_btc, isWritable: true, isSigner: false },
+        { pubkey: new PublicKey(PYTH_PRICE.DEVNET.BTC), isWritable: false, isSigner: false },
+        { pubkey: new PublicKey(CHAINLINK_PRICE.DEVNET.BTC), isWritable: false, isSigner: false },
+        { pubkey: position_account, isWritable: true, isSigner: false },
+        { pubkey: position_account, isWritable: true, isSigner: false },
+      ]).rpc()
+      assert.fail("a position passed twice should be rejected")
+    } catch (e) {
+      expect(e.error.errorCode.code).to.equal("DuplicatePositionAccount")
+    }
+  });
+
+  it("test close position", async () => {
+    let [user_account, _a] = await PublicKey.findProgramAddress(
+      [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
+      program.programId)
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    let [position_account, _c] = await PublicKey.findProgramAddress(
+      [
+        POSITION_ACCOUNT_SEED,
+        provider.wallet.publicKey.toBytes(),
+        user_account.toBytes(),
+        encode("1")
+      ],
+      program.programId,
+    )
+    var tx = await program.methods.closePosition(1).accounts({
+      configAccount: config_account,
+      userAccount: user_account,
+      marketAccount: market_account_btc,
+      positionAccount: position_account,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
     }).rpc()
+    const position = await program.account.position.fetch(position_account)
+    assert.deepEqual(position.positionStatus, { normalClosing: {} });
+    const account = await program.account.userAccount.fetch(user_account)
+    assert.strictEqual(account.marginTotal.value.toNumber(), 0);
+    assert.strictEqual(account.marginFullTotal.value.toNumber(), 0);
+    assert.strictEqual(account.openFullPositionHeaders.length, 0);
+    const fees = position.openFee.value.toNumber() + position.closeFee.value.toNumber() + position.managementFee.value.toNumber();
+    assert.strictEqual(account.profit.value.toNumber(), position.profit.value.toNumber() - fees);
+  });
+
+  it("test place and cancel limit order", async () => {
+    let [user_account, _a] = await PublicKey.findProgramAddress(
+      [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
+      program.programId)
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    const before = await program.account.userAccount.fetch(user_account)
+    let [position_account, _c] = await PublicKey.findProgramAddress(
+      [
+        POSITION_ACCOUNT_SEED,
+        provider.wallet.publicKey.toBytes(),
+        user_account.toBytes(),
+        encode(before.positionSeedOffset.toString())
+      ],
+      program.programId,
+    )
+    var tx = await program.methods.placeLimitOrder(
+      PAIR.BTC,
+      new BN(2300000),
+      4,
+      2,
+      1,
+      new BN(10000000000),
+      new BN(Math.floor(Date.now() / 1000) + 3600),
+    ).accounts({
+      userAccount: user_account,
+      marketAccount: market_account_btc,
+      positionAccount: position_account,
+    }).rpc()
+    const position = await program.account.position.fetch(position_account)
+    assert.deepEqual(position.positionStatus, { pending: {} });
+    var account = await program.account.userAccount.fetch(user_account)
+    assert.strictEqual(account.balance.value.toNumber(), before.balance.value.toNumber() - position.margin.value.toNumber());
+    tx = await program.methods.cancelOrder().accounts({
+      userAccount: user_account,
+      positionAccount: position_account,
+    }).rpc()
+    account = await program.account.userAccount.fetch(user_account)
+    assert.strictEqual(account.balance.value.toNumber(), before.balance.value.toNumber());
+  });
+
+  it("test update funding", async () => {
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    const before = await program.account.market.fetch(market_account_btc)
+    var tx = await program.methods.updateFunding(PAIR.BTC).accounts({
+      marketAccount: market_account_btc,
+    }).rpc()
+    const market = await program.account.market.fetch(market_account_btc)
+    assert.isAtLeast(market.fundingUpdateTime.toNumber(), before.fundingUpdateTime.toNumber());
+  });
+
+  it("test add and remove margin", async () => {
+    let [user_account, _a] = await PublicKey.findProgramAddress(
+      [USER_ACCOUNT_SEED, provider.wallet.publicKey.toBytes()],
+      program.programId)
+    let [market_account_btc, _b] = await PublicKey.findProgramAddress(
+      [MARKET_ACCOUNT_SEED, encode(PAIR.BTC)],
+      program.programId)
+    const before = await program.account.userAccount.fetch(user_account)
+    let [position_account, _c] = await PublicKey.findProgramAddress(
+      [
+        POSITION_ACCOUNT_SEED,
+        provider.wallet.publicKey.toBytes(),
+        user_account.toBytes(),
+        encode(before.positionSeedOffset.toString())
+      ],
+      program.programId,
+    )
+    var tx = await program.methods.openPosition(
+      PAIR.BTC,
+      new BN(2300000),
+      4,
+      2,
+      1,
+    ).accounts({
+      userAccount: user_account,
+      marketAccount: market_account_btc,
+      positionAccount: position_account,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+    }).rpc()
+    const opened = await program.account.position.fetch(position_account)
+    const accounts = {
+      userAccount: user_account,
+      marketAccount: market_account_btc,
+      positionAccount: position_account,
+      pythPriceAccount: PYTH_PRICE.DEVNET.BTC,
+      chianlinkPriceAccount: CHAINLINK_PRICE.DEVNET.BTC,
+    }
+    tx = await program.methods.addMargin(opened.margin.value).accounts(accounts).rpc()
+    var position = await program.account.position.fetch(position_account)
+    assert.strictEqual(position.margin.value.toNumber(), opened.margin.value.toNumber() * 2);
+    assert.strictEqual(position.leverage, 2);
+    tx = await program.methods.removeMargin(opened.margin.value).accounts(accounts).rpc()
+    position = await program.account.position.fetch(position_account)
+    assert.strictEqual(position.margin.value.toNumber(), opened.margin.value.toNumber());
+    assert.strictEqual(position.leverage, 4);
   });
 });
\ No newline at end of file